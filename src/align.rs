@@ -0,0 +1,173 @@
+/// What became of one symbol when typed text is lined up against the text
+/// that was sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit {
+    Correct(String),
+    Substituted {
+        expected: String,
+        typed: String,
+    },
+    /// sent but not typed
+    Dropped(String),
+    /// typed but never sent
+    Inserted(String),
+}
+
+/// Lines `typed` up against `expected` with the fewest edits, by tracing back
+/// through the Levenshtein distance table. The edits that aren't `Correct`
+/// add up to the Levenshtein distance.
+pub fn align(expected: &[String], typed: &[String]) -> Vec<Edit> {
+    let (n, m) = (expected.len(), typed.len());
+    let mut dist = vec![vec![0usize; m + 1]; n + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, d) in dist[0].iter_mut().enumerate() {
+        *d = j;
+    }
+    for i in 1..=n {
+        for j in 1..=m {
+            let cost = usize::from(expected[i - 1] != typed[j - 1]);
+            dist[i][j] = (dist[i - 1][j - 1] + cost)
+                .min(dist[i - 1][j] + 1)
+                .min(dist[i][j - 1] + 1);
+        }
+    }
+
+    // walk back from the end, preferring matches, then substitutions, so a
+    // mistyped character reads as one mistake rather than a drop and an insert
+    let mut edits = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 {
+            let same = expected[i - 1] == typed[j - 1];
+            if dist[i][j] == dist[i - 1][j - 1] + usize::from(!same) {
+                edits.push(if same {
+                    Edit::Correct(expected[i - 1].clone())
+                } else {
+                    Edit::Substituted {
+                        expected: expected[i - 1].clone(),
+                        typed: typed[j - 1].clone(),
+                    }
+                });
+                i -= 1;
+                j -= 1;
+                continue;
+            }
+        }
+        if i > 0 && dist[i][j] == dist[i - 1][j] + 1 {
+            edits.push(Edit::Dropped(expected[i - 1].clone()));
+            i -= 1;
+        } else {
+            edits.push(Edit::Inserted(typed[j - 1].clone()));
+            j -= 1;
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::morse::symbols;
+
+    fn edits(expected: &str, typed: &str) -> Vec<Edit> {
+        align(&symbols(expected), &symbols(typed))
+    }
+
+    fn correct(s: &str) -> Edit {
+        Edit::Correct(s.to_string())
+    }
+
+    // a plain two-row Levenshtein distance, to check the traceback against
+    fn distance(a: &[String], b: &[String]) -> usize {
+        let mut prev: Vec<usize> = (0..=b.len()).collect();
+        for (i, x) in a.iter().enumerate() {
+            let mut row = vec![i + 1];
+            for (j, y) in b.iter().enumerate() {
+                let cost = usize::from(x != y);
+                row.push((prev[j] + cost).min(prev[j + 1] + 1).min(row[j] + 1));
+            }
+            prev = row;
+        }
+        prev[b.len()]
+    }
+
+    #[test]
+    fn a_dropped_symbol_mid_line() {
+        assert_eq!(
+            edits("KMR", "KR"),
+            [correct("K"), Edit::Dropped("M".to_string()), correct("R")]
+        );
+    }
+
+    #[test]
+    fn an_inserted_symbol() {
+        assert_eq!(
+            edits("KM", "KSM"),
+            [correct("K"), Edit::Inserted("S".to_string()), correct("M")]
+        );
+    }
+
+    #[test]
+    fn a_substitution() {
+        assert_eq!(
+            edits("KMR", "KSR"),
+            [
+                correct("K"),
+                Edit::Substituted {
+                    expected: "M".to_string(),
+                    typed: "S".to_string()
+                },
+                correct("R")
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_typed() {
+        assert_eq!(
+            edits("KM", ""),
+            [
+                Edit::Dropped("K".to_string()),
+                Edit::Dropped("M".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn a_prosign_is_one_symbol() {
+        assert_eq!(
+            edits("K <AR>", "K <AK>"),
+            [
+                correct("K"),
+                correct(" "),
+                Edit::Substituted {
+                    expected: "<AR>".to_string(),
+                    typed: "<AK>".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn mistakes_add_up_to_the_levenshtein_distance() {
+        let pairs = [
+            ("KMRSU AEOTN", "KMSU AEIOTN"),
+            ("PARIS PARIS", "PRAIS PARIZ"),
+            ("CQ DE <KN>", "CQ D <KN> K"),
+            ("", "ABC"),
+            ("ABC", ""),
+            ("KITTEN", "SITTING"),
+        ];
+        for (expected, typed) in pairs {
+            let (expected, typed) = (symbols(expected), symbols(typed));
+            let mistakes = align(&expected, &typed)
+                .iter()
+                .filter(|edit| !matches!(edit, Edit::Correct(_)))
+                .count();
+            assert_eq!(mistakes, distance(&expected, &typed));
+        }
+    }
+}
@@ -1,22 +1,53 @@
-use std::{error::Error, io, time::Duration};
+use std::{
+    error::Error,
+    io,
+    time::{Duration, Instant},
+};
 
-use crate::{audio::play_lesson_audio, lesson, morse, scores::ScoreData, ui::draw_ui};
+use crate::{
+    align::{self, Edit},
+    audio::{
+        AudioSettings, LessonHandle, MorseAudio, SAMPLE_RATE, play_keyer, play_lesson_audio,
+        play_sidetone,
+    },
+    fist::FistReport,
+    keyer::KeyerConfig,
+    lesson::{self, LessonConfig},
+    morse::{self, Alphabet},
+    scores::{self, ScoreData, ScoreRecord, SendingScore, SpeedChange},
+    sending::{Lever, SendingSession},
+    settings::{Field, Settings},
+    ui::draw_ui,
+};
 
 use ratatui::{
     crossterm::{
-        event::{self, Event, KeyCode, KeyEventKind},
+        event::{
+            self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+            PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        },
         execute,
-        terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+        terminal::{
+            EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+            supports_keyboard_enhancement,
+        },
     },
-    style::{Color, Style},
-    text::Span,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
 };
-use strsim::levenshtein;
+
+const MAX_QRM_STATIONS: usize = 3;
+
+type Terminal = ratatui::Terminal<ratatui::backend::CrosstermBackend<io::Stdout>>;
 
 pub enum Mode {
     PickingLesson,
     TypingLesson,
     LetterPractice,
+    Sending,
+    SendingReport,
+    Confusions,
+    Settings,
 }
 
 pub struct App {
@@ -25,34 +56,69 @@ pub struct App {
     pub scroll_offset: usize,
     pub user_input: String,
     pub scores: ScoreData,
-    pub wpm: u32,
-    pub effective_wpm: u32,
-    pub freq: f32,
+    pub audio: AudioSettings,
+    pub keyer: KeyerConfig,
+    pub lesson: LessonConfig,
+    pub alphabet: Alphabet,
     pub sink: Option<rodio::Sink>,
     pub stream: Option<rodio::OutputStream>,
     pub current_practice: String,
     pub visible_rows: usize,
-    pub highlighted_results: Option<Vec<Span<'static>>>,
+    /// what was typed over what was sent, lined up symbol by symbol
+    pub highlighted_results: Option<[Line<'static>; 2]>,
     pub letter_practice: String,
+    pub sending: Option<SendingSession>,
+    /// whether the terminal reports key releases, which the straight key needs
+    pub key_release_events: bool,
+    /// why the last key did nothing, shown until the next key
+    pub notice: Option<String>,
+    pub last_accuracy: Option<u32>,
+    pub fist_report: Option<FistReport>,
+    pub confusion_selected: usize,
+    /// the pair being drilled, when the lesson is a drill
+    pub drill: Option<(String, String)>,
+    pub typing_started: Option<Instant>,
+    pub settings_selected: usize,
+    /// the lesson audio, which started at group `playing_from`
+    pub playback: Option<LessonHandle>,
+    pub playing_from: usize,
+    /// the sound of the lesson or practice playing, which the speed keys
+    /// change for that session only
+    pub session_audio: AudioSettings,
+    pub speed_changes: Vec<SpeedChange>,
 }
 
 impl App {
-    pub fn new(wpm: u32, effective_wpm: u32, freq: f32) -> Result<Self, Box<dyn Error>> {
+    pub fn new(settings: Settings) -> Result<Self, Box<dyn Error>> {
         Ok(Self {
             mode: Mode::PickingLesson,
             selected: 0,
             scroll_offset: 0,
             user_input: String::new(),
-            scores: confy::load("moser", None)?,
-            wpm,
-            effective_wpm,
-            freq,
+            scores: ScoreData::load()?,
+            audio: settings.audio,
+            keyer: settings.keyer,
+            lesson: settings.lesson,
+            alphabet: settings.alphabet,
             sink: None,
             stream: None,
             current_practice: String::new(),
             visible_rows: 0,
             highlighted_results: None,
             letter_practice: String::new(),
+            sending: None,
+            key_release_events: false,
+            notice: None,
+            last_accuracy: None,
+            fist_report: None,
+            confusion_selected: 0,
+            drill: None,
+            typing_started: None,
+            settings_selected: 0,
+            playback: None,
+            playing_from: 0,
+            session_audio: settings.audio,
+            speed_changes: Vec::new(),
         })
     }
 
@@ -60,32 +126,91 @@ impl App {
         enable_raw_mode()?;
         let mut stdout = io::stdout();
         execute!(stdout, EnterAlternateScreen)?;
+        self.key_release_events = supports_keyboard_enhancement().unwrap_or(false);
         let backend = ratatui::backend::CrosstermBackend::new(stdout);
         let mut terminal = ratatui::Terminal::new(backend)?;
 
-        let total_lessons = morse::KOCH_SEQUENCE.len() - 1;
+        // the terminal is put back however the loop ends
+        let mut keys_reported = false;
+        let result = self.event_loop(&mut terminal, &mut keys_reported);
+
+        if keys_reported {
+            execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+        }
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        result
+    }
 
+    fn event_loop(
+        &mut self,
+        terminal: &mut Terminal,
+        keys_reported: &mut bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let total_lessons = self.alphabet.total_lessons();
         loop {
+            // plain keys only report their releases as escape codes, which
+            // would also turn shifted characters into their base keys, so
+            // that's only asked for while sending
+            if self.sending.is_some() != *keys_reported {
+                *keys_reported = !*keys_reported;
+                if *keys_reported {
+                    execute!(
+                        terminal.backend_mut(),
+                        PushKeyboardEnhancementFlags(
+                            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                                | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                        )
+                    )?;
+                } else {
+                    execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+                }
+            }
             terminal.draw(|f| draw_ui(f, self, total_lessons))?;
 
-            if event::poll(Duration::from_millis(50))? {
-                if let Event::Key(key) = event::read()? {
-                    if key.kind == KeyEventKind::Press {
-                        if self.handle_key(key.code, total_lessons)? {
-                            break;
-                        }
+            // the straight key needs finer timing than typing does
+            let poll = if self.sending.is_some() { 5 } else { 50 };
+            if event::poll(Duration::from_millis(poll))?
+                && let Event::Key(key) = event::read()?
+            {
+                if let Some(session) = &mut self.sending
+                    && let Some(lever) = lever(key.code)
+                {
+                    match key.kind {
+                        KeyEventKind::Press => session.key_down(lever, Instant::now()),
+                        KeyEventKind::Release => session.key_up(lever, Instant::now()),
+                        KeyEventKind::Repeat => {}
+                    }
+                } else if key.kind == KeyEventKind::Press {
+                    // a failed action is reported rather than ending the
+                    // program, e.g. when there's no audio device
+                    match self.handle_key(key, total_lessons) {
+                        Ok(true) => break,
+                        Ok(false) => {}
+                        Err(err) => self.notice = Some(err.to_string()),
                     }
                 }
             }
+            if let Some(session) = &mut self.sending {
+                session.tick(Instant::now());
+            }
         }
-
-        disable_raw_mode()?;
-        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-        terminal.show_cursor()?;
         Ok(())
     }
 
-    fn handle_key(&mut self, code: KeyCode, total_lessons: usize) -> Result<bool, Box<dyn Error>> {
+    fn handle_key(&mut self, key: KeyEvent, total_lessons: usize) -> Result<bool, Box<dyn Error>> {
+        let code = key.code;
+        // letters are typed in a lesson, so its controls need Ctrl
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        self.notice = None;
+        if matches!(self.mode, Mode::TypingLesson | Mode::LetterPractice)
+            && let Some((field, up)) = speed_key(code)
+        {
+            self.change_speed(field, up);
+            return Ok(false);
+        }
         match self.mode {
             Mode::PickingLesson => match code {
                 KeyCode::Char('q') => return Ok(true), // quit
@@ -102,36 +227,99 @@ impl App {
                     }
                 }
                 KeyCode::Enter => {
-                    self.user_input.clear();
-                    self.current_practice.clear();
+                    let timing = MorseAudio::from_settings(&self.audio, SAMPLE_RATE);
+                    let text = lesson::lesson_text(
+                        self.selected + 1,
+                        self.alphabet,
+                        &self.lesson,
+                        &self.scores,
+                        |text| timing.duration(text),
+                    );
+                    self.drill = None;
+                    self.start_typing(text)?;
+                }
+                KeyCode::Char('c') => {
+                    self.confusion_selected = 0;
+                    self.mode = Mode::Confusions;
+                }
+                KeyCode::Char('o') => {
+                    self.settings_selected = 0;
+                    self.mode = Mode::Settings;
+                }
+                KeyCode::Char('p') => {
+                    self.mode = Mode::LetterPractice;
+                    self.letter_practice = lesson::practice_text(self.selected + 1, self.alphabet);
+                    self.session_audio = self.audio;
+                    let (stream, sink, playback) =
+                        play_lesson_audio(&self.letter_practice, &self.audio)?;
+                    self.stream = Some(stream);
+                    self.sink = Some(sink);
+                    self.playback = Some(playback);
+                }
+                KeyCode::Char('s') | KeyCode::Char('a') if !self.key_release_events => {
+                    self.notice = Some(
+                        "Sending needs a terminal that reports key releases (kitty keyboard protocol)"
+                            .to_string(),
+                    );
+                }
+                KeyCode::Char('s') => {
                     self.highlighted_results = None;
-                    self.current_practice = lesson::lesson_text(self.selected + 1);
-                    let (stream, sink) = play_lesson_audio(
-                        &self.current_practice,
-                        self.wpm,
-                        self.freq,
-                        self.effective_wpm,
-                    )?;
+                    self.last_accuracy = None;
+                    let target = lesson::sending_text(self.selected + 1, self.alphabet);
+                    let (stream, sink, key) = play_sidetone(&self.audio)?;
+                    self.sending = Some(SendingSession::straight(
+                        target,
+                        self.audio.wpm,
+                        self.alphabet,
+                        key,
+                    ));
                     self.stream = Some(stream);
                     self.sink = Some(sink);
-                    self.mode = Mode::TypingLesson;
+                    self.mode = Mode::Sending;
                 }
-                KeyCode::Char('p') => {
-                    self.mode = Mode::LetterPractice;
-                    self.letter_practice = lesson::practice_text(self.selected + 1);
-                    let (stream, sink) = play_lesson_audio(
-                        &self.letter_practice,
-                        self.wpm,
-                        self.freq,
-                        self.effective_wpm,
-                    )?;
+                KeyCode::Char('a') => {
+                    self.highlighted_results = None;
+                    self.last_accuracy = None;
+                    let target = lesson::sending_text(self.selected + 1, self.alphabet);
+                    let (stream, sink, keyer) = play_keyer(&self.audio, self.keyer)?;
+                    self.sending = Some(SendingSession::iambic(
+                        target,
+                        self.keyer.wpm(self.audio.wpm),
+                        self.alphabet,
+                        keyer,
+                    ));
                     self.stream = Some(stream);
                     self.sink = Some(sink);
+                    self.mode = Mode::Sending;
+                }
+                KeyCode::Char('n') => self.audio.noise.kind = self.audio.noise.kind.next(),
+                KeyCode::Char('i') => {
+                    self.audio.qrm_stations = (self.audio.qrm_stations + 1) % (MAX_QRM_STATIONS + 1)
                 }
                 _ => {}
             },
             Mode::TypingLesson => match code {
-                KeyCode::Char(c) => self.user_input.push(c),
+                KeyCode::Char('p') if ctrl => {
+                    if let Some(sink) = &self.sink {
+                        if sink.is_paused() {
+                            sink.play();
+                        } else {
+                            sink.pause();
+                        }
+                    }
+                }
+                KeyCode::Char('r') if ctrl => self.play_from(self.current_group())?,
+                KeyCode::Char('n') if ctrl => {
+                    self.user_input.clear();
+                    self.typing_started = Some(Instant::now());
+                    self.session_audio = self.audio;
+                    self.speed_changes.clear();
+                    self.play_from(0)?;
+                }
+                KeyCode::Char(_) if ctrl => {}
+                KeyCode::Char(c) => self
+                    .user_input
+                    .push_str(&self.alphabet.normalize(&c.to_string())),
                 KeyCode::Backspace => {
                     self.user_input.pop();
                 }
@@ -156,55 +344,417 @@ impl App {
                     }
                     self.stream.take();
                 }
+                KeyCode::Char('+') | KeyCode::Char('=') => self.change_speed(Field::CharWpm, true),
+                KeyCode::Char('-') => self.change_speed(Field::CharWpm, false),
+                _ => {}
+            },
+            Mode::Sending => match code {
+                KeyCode::Enter => {
+                    self.finish_sending()?;
+                    self.mode = if self.fist_report.is_some() {
+                        Mode::SendingReport
+                    } else {
+                        Mode::PickingLesson
+                    };
+                }
+                KeyCode::Esc => {
+                    self.sending = None;
+                    self.mode = Mode::PickingLesson;
+                    if let Some(sink) = self.sink.take() {
+                        sink.stop();
+                    }
+                    self.stream.take();
+                }
+                _ => {}
+            },
+            Mode::SendingReport => {
+                if matches!(code, KeyCode::Esc | KeyCode::Enter) {
+                    self.mode = Mode::PickingLesson;
+                }
+            }
+            Mode::Confusions => {
+                let pairs = self.scores.confused_pairs();
+                match code {
+                    KeyCode::Esc | KeyCode::Char('q') => self.mode = Mode::PickingLesson,
+                    KeyCode::Down | KeyCode::Char('j') if !pairs.is_empty() => {
+                        self.confusion_selected = (self.confusion_selected + 1) % pairs.len();
+                    }
+                    KeyCode::Up | KeyCode::Char('k') if !pairs.is_empty() => {
+                        self.confusion_selected =
+                            (self.confusion_selected + pairs.len() - 1) % pairs.len();
+                    }
+                    KeyCode::Enter => {
+                        if let Some((a, b, _)) = pairs.into_iter().nth(self.confusion_selected) {
+                            let timing = MorseAudio::from_settings(&self.audio, SAMPLE_RATE);
+                            let text =
+                                lesson::drill_text(&[a.clone(), b.clone()], &self.lesson, |text| {
+                                    timing.duration(text)
+                                });
+                            self.start_typing(text)?;
+                            self.drill = Some((a, b));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Mode::Settings => match code {
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    if let Some(sink) = self.sink.take() {
+                        sink.stop();
+                    }
+                    self.stream.take();
+                    self.mode = Mode::PickingLesson;
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.settings_selected = (self.settings_selected + 1) % Field::ALL.len();
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.settings_selected =
+                        (self.settings_selected + Field::ALL.len() - 1) % Field::ALL.len();
+                }
+                KeyCode::Left | KeyCode::Right | KeyCode::Char('h') | KeyCode::Char('l') => {
+                    let field = Field::ALL[self.settings_selected];
+                    let mut settings = self.settings();
+                    field.adjust(
+                        &mut settings,
+                        matches!(code, KeyCode::Right | KeyCode::Char('l')),
+                    );
+                    self.audio = settings.audio;
+                    self.keyer = settings.keyer;
+                    self.lesson = settings.lesson;
+                    // only the change is saved; options given for this run
+                    // and the picker's toggles stay out of the file
+                    let mut saved = Settings::load()?;
+                    field.copy(&settings, &mut saved);
+                    saved.store()?;
+                    if field.audible() {
+                        self.preview()?;
+                    }
+                }
+                KeyCode::Char('p') => self.preview()?,
                 _ => {}
             },
         }
         Ok(false)
     }
 
-    fn finish_typing(&mut self) -> Result<(), Box<dyn Error>> {
-        let lesson_num = self.selected + 1;
-        let typed = self.user_input.trim().to_uppercase();
-        let practice = self.current_practice.to_uppercase();
-
-        let mut spans = Vec::new();
-        for (uc, pc) in typed.chars().zip(practice.chars()) {
-            if uc == pc {
-                spans.push(Span::styled(
-                    uc.to_string(),
-                    Style::default().fg(Color::Green),
-                ))
-            } else {
-                spans.push(Span::styled(
-                    uc.to_string(),
-                    Style::default().fg(Color::Red),
-                ))
-            }
+    /// Everything that can be saved, as it stands now.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            audio: self.audio,
+            keyer: self.keyer,
+            alphabet: self.alphabet,
+            lesson: self.lesson,
         }
-        if typed.len() < practice.len() {
-            for pc in practice.chars().skip(typed.len()) {
-                spans.push(Span::styled(
-                    pc.to_string(),
-                    Style::default().fg(Color::Red),
-                ))
+    }
+
+    /// Plays a short sample with the current settings, cutting off any
+    /// sample still playing.
+    fn preview(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        let (stream, sink, _) = play_lesson_audio("VVV", &self.audio)?;
+        self.stream = Some(stream);
+        self.sink = Some(sink);
+        Ok(())
+    }
+
+    fn start_typing(&mut self, text: String) -> Result<(), Box<dyn Error>> {
+        self.user_input.clear();
+        self.highlighted_results = None;
+        self.last_accuracy = None;
+        self.current_practice = text;
+        self.typing_started = Some(Instant::now());
+        self.session_audio = self.audio;
+        self.speed_changes.clear();
+        self.play_from(0)?;
+        self.mode = Mode::TypingLesson;
+        Ok(())
+    }
+
+    /// Plays the lesson from the start of group `first` to the end, cutting
+    /// off whatever is playing.
+    fn play_from(&mut self, first: usize) -> Result<(), Box<dyn Error>> {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        let text = self
+            .current_practice
+            .split(' ')
+            .skip(first)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (stream, sink, playback) = play_lesson_audio(&text, &self.session_audio)?;
+        self.stream = Some(stream);
+        self.sink = Some(sink);
+        self.playback = Some(playback);
+        self.playing_from = first;
+        Ok(())
+    }
+
+    /// Steps the speed or pitch of what's left of the session, from the next
+    /// character on.
+    fn change_speed(&mut self, field: Field, up: bool) {
+        let Some(audio) = step_speed(self.session_audio, field, up) else {
+            return;
+        };
+        self.session_audio = audio;
+        if let Some(playback) = &self.playback {
+            playback.retime(MorseAudio::from_settings(&self.session_audio, SAMPLE_RATE));
+        }
+        if matches!(self.mode, Mode::TypingLesson) {
+            self.speed_changes.push(SpeedChange {
+                at_secs: self
+                    .typing_started
+                    .map_or(0.0, |started| started.elapsed().as_secs_f32()),
+                wpm: self.session_audio.wpm,
+                effective_wpm: self.session_audio.effective_wpm,
+                tone_freq: self.session_audio.tone_freq,
+            });
+        }
+    }
+
+    /// The group playing now, or the last one heard if the audio is between
+    /// groups or has finished.
+    fn current_group(&self) -> usize {
+        let started = self.playback.as_ref().map_or(0, LessonHandle::progress);
+        let groups: Vec<&str> = self.current_practice.split(' ').collect();
+        let mut end = 0;
+        for (i, group) in groups.iter().enumerate().skip(self.playing_from) {
+            // the space after a group still belongs to it
+            end += group.chars().count() + 1;
+            if started <= end {
+                return i;
             }
         }
-        self.highlighted_results = Some(spans);
-
-        let distance = levenshtein(&typed, &self.current_practice);
-        let max_len = self.current_practice.len().max(typed.len());
-        let accuracy = if max_len == 0 {
-            0
-        } else {
-            ((max_len - distance) * 100 / max_len) as u32
+        groups.len().saturating_sub(1)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.sink.as_ref().is_some_and(|sink| sink.is_paused())
+    }
+
+    fn finish_sending(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        self.stream.take();
+        let Some(mut session) = self.sending.take() else {
+            return Ok(());
         };
-        self.scores
-            .lessons
-            .entry(lesson_num.to_string())
-            .or_default()
-            .push(accuracy);
-        confy::store("moser", None, &self.scores)?;
+        let sent = session.finish();
+        let (edits, accuracy) = compare(&sent, &session.target, self.alphabet);
+        self.highlighted_results = Some(highlight(&edits));
+        self.last_accuracy = Some(accuracy);
+        self.current_practice = session.target;
+
+        self.fist_report = FistReport::analyze(session.decoder.chars());
+        if let Some(report) = &self.fist_report {
+            self.scores
+                .sending
+                .entry(self.alphabet.lesson_key(self.selected + 1))
+                .or_default()
+                .push(SendingScore {
+                    timestamp: scores::unix_time(),
+                    accuracy,
+                    wpm: session.decoder.wpm(),
+                    dah_ratio: report.dah_ratio,
+                    element_gap: report.element_gap,
+                    char_gap: report.char_gap,
+                    word_gap: report.word_gap,
+                });
+            self.scores.store()?;
+        }
+        Ok(())
+    }
+
+    fn finish_typing(&mut self) -> Result<(), Box<dyn Error>> {
+        let lesson_num = self.selected + 1;
+        let (edits, accuracy) = compare(
+            self.user_input.trim(),
+            &self.current_practice,
+            self.alphabet,
+        );
+        self.highlighted_results = Some(highlight(&edits));
+        self.last_accuracy = Some(accuracy);
+        self.scores.record_chars(&edits);
+        let speed_changes = std::mem::take(&mut self.speed_changes);
+        // a drill isn't the lesson, so it stays off the lesson's chart
+        if self.drill.is_none() {
+            let record = ScoreRecord {
+                timestamp: Some(scores::unix_time()),
+                accuracy,
+                wpm: Some(self.audio.wpm),
+                effective_wpm: Some(self.audio.effective_wpm),
+                tone_freq: Some(self.audio.tone_freq),
+                duration_secs: self
+                    .typing_started
+                    .map(|started| started.elapsed().as_secs_f32()),
+                expected: self.current_practice.clone(),
+                typed: self.user_input.trim().to_string(),
+                speed_changes,
+            };
+            self.scores
+                .lessons
+                .entry(self.alphabet.lesson_key(lesson_num))
+                .or_default()
+                .push(record);
+        }
+        self.scores.store()?;
 
         Ok(())
     }
 }
+
+/// The keys that work the straight key and the paddles.
+fn lever(code: KeyCode) -> Option<Lever> {
+    match code {
+        KeyCode::Char(' ') => Some(Lever::Straight),
+        KeyCode::Char('z') | KeyCode::Char('[') => Some(Lever::Dit),
+        KeyCode::Char('x') | KeyCode::Char(']') => Some(Lever::Dah),
+        _ => None,
+    }
+}
+
+/// The keys that change the speed and pitch while a session plays, with
+/// which way they step.
+fn speed_key(code: KeyCode) -> Option<(Field, bool)> {
+    match code {
+        KeyCode::Up => Some((Field::CharWpm, true)),
+        KeyCode::Down => Some((Field::CharWpm, false)),
+        KeyCode::Right => Some((Field::EffectiveWpm, true)),
+        KeyCode::Left => Some((Field::EffectiveWpm, false)),
+        KeyCode::PageUp => Some((Field::Tone, true)),
+        KeyCode::PageDown => Some((Field::Tone, false)),
+        _ => None,
+    }
+}
+
+/// The session's audio after a speed key, or `None` if it's already as far
+/// as that key goes, so nothing is retimed or recorded.
+fn step_speed(audio: AudioSettings, field: Field, up: bool) -> Option<AudioSettings> {
+    let mut settings = Settings {
+        audio,
+        ..Settings::default()
+    };
+    field.adjust(&mut settings, up);
+    (settings.audio != audio).then_some(settings.audio)
+}
+
+/// Lines `typed` up against `expected` and scores it as a percentage.
+fn compare(typed: &str, expected: &str, alphabet: Alphabet) -> (Vec<Edit>, u32) {
+    // prosigns are one symbol each, so `<AR>` typed as `<AK>` is one miss
+    let typed = morse::symbols(&alphabet.normalize(typed));
+    let practice = morse::symbols(&alphabet.normalize(expected));
+    let edits = align::align(&practice, &typed);
+
+    let distance = edits
+        .iter()
+        .filter(|edit| !matches!(edit, Edit::Correct(_)))
+        .count();
+    let max_len = practice.len().max(typed.len());
+    let accuracy = ((max_len - distance) * 100)
+        .checked_div(max_len)
+        .unwrap_or(0) as u32;
+    (edits, accuracy)
+}
+
+/// Shows an alignment as the typed line over the sent line, padded so each
+/// symbol sits over the one it was matched with. Substitutions are red,
+/// dropped symbols yellow with a gap where they were missed, and inserted
+/// symbols crossed out in magenta over a gap.
+fn highlight(edits: &[Edit]) -> [Line<'static>; 2] {
+    let correct = Style::default().fg(Color::Green);
+    let substituted = Style::default().fg(Color::Red).add_modifier(Modifier::BOLD);
+    let dropped = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let inserted = Style::default()
+        .fg(Color::Magenta)
+        .add_modifier(Modifier::CROSSED_OUT);
+
+    let mut typed_line = Vec::new();
+    let mut sent_line = Vec::new();
+    for edit in edits {
+        let ((typed, typed_style), (sent, sent_style)) = match edit {
+            Edit::Correct(s) => ((s.as_str(), correct), (s.as_str(), correct)),
+            Edit::Substituted { expected, typed } => (
+                (typed.as_str(), substituted),
+                (expected.as_str(), substituted),
+            ),
+            Edit::Dropped(s) => (("_", dropped), (s.as_str(), dropped)),
+            Edit::Inserted(s) => ((s.as_str(), inserted), ("", Style::default())),
+        };
+        // pad by display width, since kana take up two columns
+        let typed = Span::styled(typed.to_string(), typed_style);
+        let sent = Span::styled(sent.to_string(), sent_style);
+        let width = typed.width().max(sent.width());
+        typed_line.push(pad(typed, width));
+        sent_line.push(pad(sent, width));
+    }
+    [Line::from(typed_line), Line::from(sent_line)]
+}
+
+fn pad(span: Span<'static>, width: usize) -> Span<'static> {
+    let fill = " ".repeat(width - span.width());
+    Span::styled(format!("{}{}", span.content, fill), span.style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlighted_lines_line_up_with_wide_characters() {
+        let edits = [
+            Edit::Substituted {
+                expected: "ア".to_string(),
+                typed: "K".to_string(),
+            },
+            Edit::Dropped("イ".to_string()),
+            Edit::Correct("M".to_string()),
+        ];
+        let [typed, sent] = highlight(&edits);
+        assert_eq!(typed.width(), sent.width());
+        for (t, s) in typed.spans.iter().zip(&sent.spans) {
+            assert_eq!(t.width(), s.width());
+        }
+    }
+
+    #[test]
+    fn speed_keys_never_go_the_wrong_way() {
+        let speed = |audio: &AudioSettings| (audio.wpm, audio.effective_wpm, audio.tone_freq);
+        let keys = [
+            KeyCode::Up,
+            KeyCode::Down,
+            KeyCode::Right,
+            KeyCode::Left,
+            KeyCode::PageUp,
+            KeyCode::PageDown,
+        ];
+        // settings the command line allows but the settings screen doesn't
+        for (wpm, effective_wpm, tone_freq) in [(3, 2, 1500.0), (80, 80, 100.0)] {
+            let audio = AudioSettings {
+                wpm,
+                effective_wpm,
+                tone_freq,
+                ..AudioSettings::default()
+            };
+            for key in keys {
+                let (field, up) = speed_key(key).unwrap();
+                let Some(stepped) = step_speed(audio, field, up) else {
+                    continue;
+                };
+                let (before, after) = (speed(&audio), speed(&stepped));
+                if up {
+                    assert!(after.0 >= before.0 && after.1 >= before.1, "{:?}", key);
+                    assert!(after.2 >= before.2, "{:?}", key);
+                } else {
+                    assert!(after.0 <= before.0 && after.1 <= before.1, "{:?}", key);
+                    assert!(after.2 <= before.2, "{:?}", key);
+                }
+            }
+        }
+    }
+}
@@ -1,98 +1,584 @@
-use std::error::Error;
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    time::Duration,
+};
 
-use rodio::{Sink, buffer::SamplesBuffer};
+use rand::Rng;
+use rodio::{ChannelCount, SampleRate, Sink, Source};
+use serde::{Deserialize, Serialize};
 
-use crate::morse;
+use crate::{
+    band::{BandNoise, Fading, FadingConfig, NoiseConfig},
+    fist::{Fist, FistProfile},
+    keyer::{KeyerConfig, KeyerHandle, KeyerSource},
+    lesson,
+    morse::{self, UnknownSymbols},
+};
+
+pub const SAMPLE_RATE: usize = 44_100;
+pub const TONE_AMPLITUDE: f32 = 0.9;
 
 pub fn sine_wave_samples(freq: f32, duration_sec: f32, sample_rate: usize) -> Vec<f32> {
     let sample_count = (duration_sec * sample_rate as f32) as usize;
     (0..sample_count)
         .map(|i| {
             let t = i as f32 / sample_rate as f32;
-            0.9 * (2.0 * std::f32::consts::PI * freq * t).sin()
+            TONE_AMPLITUDE * (2.0 * std::f32::consts::PI * freq * t).sin()
         })
         .collect()
 }
 
+/// Shapes the start and end of a keyed element with a raised-cosine ramp so
+/// the tone doesn't switch on and off at full amplitude (key clicks).
+pub fn apply_envelope(samples: &mut [f32], rise_time_sec: f32, sample_rate: usize) {
+    let len = samples.len();
+    let ramp = ((rise_time_sec * sample_rate as f32) as usize).min(len / 2);
+    for i in 0..ramp {
+        let gain = 0.5 - 0.5 * (std::f32::consts::PI * i as f32 / ramp as f32).cos();
+        samples[i] *= gain;
+        samples[len - 1 - i] *= gain;
+    }
+}
+
+pub fn keyed_tone_samples(
+    freq: f32,
+    duration_sec: f32,
+    rise_time_sec: f32,
+    sample_rate: usize,
+) -> Vec<f32> {
+    let mut samples = sine_wave_samples(freq, duration_sec, sample_rate);
+    apply_envelope(&mut samples, rise_time_sec, sample_rate);
+    samples
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub wpm: u32,
+    pub effective_wpm: u32,
+    pub tone_freq: f32,
+    pub rise_time: f32,
+    pub noise: NoiseConfig,
+    pub fading: FadingConfig,
+    pub fist: Fist,
+    pub qrm_stations: usize,
+    /// playback volume, from 0 to 1
+    pub volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            wpm: 20,
+            effective_wpm: 15,
+            tone_freq: 600.0,
+            rise_time: 5.0,
+            noise: NoiseConfig::default(),
+            fading: FadingConfig::default(),
+            fist: Fist::default(),
+            qrm_stations: 0,
+            volume: 1.0,
+        }
+    }
+}
+
 pub struct MorseAudio {
     pub dit: Vec<f32>,
     pub dah: Vec<f32>,
-    pub gap1: Vec<f32>,
-    pub gap3: Vec<f32>,
-    pub gap7: Vec<f32>,
+    pub dit_len: f32,
+    pub dah_len: f32,
+    pub gap1_len: f32,
+    pub gap3_len: f32,
+    pub gap7_len: f32,
+    pub tone_freq: f32,
+    pub rise_time: f32,
+    pub fist: Fist,
     pub sample_rate: usize,
 }
 
 impl MorseAudio {
-    pub fn new(wpm: u32, effective_wpm: u32, tone_freq: f32, sample_rate: usize) -> Self {
-        let dit_len = 1.2 / wpm as f32;
-        let dah_len = 3.0 * dit_len;
+    pub fn new(
+        wpm: u32,
+        effective_wpm: u32,
+        tone_freq: f32,
+        rise_time_ms: f32,
+        fist: Fist,
+        sample_rate: usize,
+    ) -> Self {
+        let unit = 1.2 / wpm as f32;
+        let rise_time = rise_time_ms / 1000.0;
 
-        let standard_word_time = 50.0 * dit_len;
+        let standard_word_time = 50.0 * unit;
         let effective_word_time = 60.0 / effective_wpm as f32;
         let stretch = (effective_word_time / standard_word_time).max(1.0);
-        let gap1_len = dit_len * stretch;
-        let gap3_len = 3.0 * dit_len * stretch;
-        let gap7_len = 7.0 * dit_len * stretch;
+
+        // weighting moves time from the gap after each mark into the mark
+        let weight = (fist.weight - 1.0) * unit;
+        let dit_len = unit + weight;
+        let dah_len = fist.dah_ratio * unit + weight;
+        let gap1_len = (unit * stretch - weight).max(0.0);
+        let gap3_len = (3.0 * unit * stretch - weight).max(0.0);
+        let gap7_len = 7.0 * unit * stretch;
 
         Self {
-            dit: sine_wave_samples(tone_freq, dit_len, sample_rate),
-            dah: sine_wave_samples(tone_freq, dah_len, sample_rate),
-            gap1: vec![0.0; (gap1_len * sample_rate as f32) as usize],
-            gap3: vec![0.0; (gap3_len * sample_rate as f32) as usize],
-            gap7: vec![0.0; (gap7_len * sample_rate as f32) as usize],
+            dit: keyed_tone_samples(tone_freq, dit_len, rise_time, sample_rate),
+            dah: keyed_tone_samples(tone_freq, dah_len, rise_time, sample_rate),
+            dit_len,
+            dah_len,
+            gap1_len,
+            gap3_len,
+            gap7_len,
+            tone_freq,
+            rise_time,
+            fist,
             sample_rate,
         }
     }
 
-    pub fn morse_to_audio(&self, morse_str: &str) -> Vec<f32> {
-        let mut out = Vec::new();
+    pub fn from_settings(settings: &AudioSettings, sample_rate: usize) -> Self {
+        Self::new(
+            settings.wpm,
+            settings.effective_wpm,
+            settings.tone_freq,
+            settings.rise_time,
+            settings.fist,
+            sample_rate,
+        )
+    }
+
+    /// Appends the samples for one character's code, including the gap that
+    /// follows it, to `out`.
+    pub fn write_code(&self, morse_str: &str, out: &mut Vec<f32>) {
+        let mut rng = rand::rng();
         if morse_str == " " {
-            out.extend(&self.gap7);
-        } else {
-            for sym in morse_str.chars() {
-                match sym {
-                    '.' => out.extend(&self.dit),
-                    '-' => out.extend(&self.dah),
-                    _ => {}
+            self.write_gap(self.gap7_len, out, &mut rng);
+            return;
+        }
+        for (i, sym) in morse_str.chars().enumerate() {
+            if i > 0 {
+                self.write_gap(self.gap1_len, out, &mut rng);
+            }
+            match sym {
+                '.' => self.write_mark(&self.dit, self.dit_len, out, &mut rng),
+                '-' => self.write_mark(&self.dah, self.dah_len, out, &mut rng),
+                _ => {}
+            }
+        }
+        self.write_gap(self.gap3_len, out, &mut rng);
+    }
+
+    /// How long `text` takes to send at this timing, leaving out the jitter.
+    pub fn duration(&self, text: &str) -> f32 {
+        let map = morse::morse_map();
+        morse::symbols(text)
+            .iter()
+            .filter_map(|symbol| morse::encode_symbol(symbol, &map))
+            .map(|code| {
+                if code == " " {
+                    return self.gap7_len;
                 }
-                out.extend(&self.gap1);
+                let marks: f32 = code
+                    .chars()
+                    .map(|sym| {
+                        if sym == '-' {
+                            self.dah_len
+                        } else {
+                            self.dit_len
+                        }
+                    })
+                    .sum();
+                marks + (code.len() - 1) as f32 * self.gap1_len + self.gap3_len
+            })
+            .sum()
+    }
+
+    fn write_mark(&self, nominal: &[f32], len: f32, out: &mut Vec<f32>, rng: &mut impl Rng) {
+        if self.fist.jitter <= 0.0 {
+            out.extend(nominal);
+        } else {
+            let len = self.fist.jittered(len, rng);
+            out.extend(keyed_tone_samples(
+                self.tone_freq,
+                len,
+                self.rise_time,
+                self.sample_rate,
+            ));
+        }
+    }
+
+    fn write_gap(&self, len: f32, out: &mut Vec<f32>, rng: &mut impl Rng) {
+        let len = self.fist.jittered(len, rng);
+        out.resize(out.len() + (len * self.sample_rate as f32) as usize, 0.0);
+    }
+}
+
+/// A rodio `Source` that keys the text one character at a time as playback
+/// reaches it, rather than rendering the whole session up front. The text can
+/// be any iterator, so it may be generated on the fly or never end.
+pub struct MorseSource {
+    audio: MorseAudio,
+    text: Box<dyn Iterator<Item = char> + Send>,
+    symbols: VecDeque<String>,
+    map: HashMap<char, &'static str>,
+    buffer: Vec<f32>,
+    pos: usize,
+    noise: Option<BandNoise>,
+    fading: Option<Fading>,
+    amplitude: f32,
+    progress: Option<Arc<AtomicUsize>>,
+    retime: Option<Receiver<MorseAudio>>,
+}
+
+/// The app's hold on a lesson while it plays: how far it has got, and a way
+/// to change the speed and pitch of the rest.
+pub struct LessonHandle {
+    progress: Arc<AtomicUsize>,
+    retime: Sender<MorseAudio>,
+}
+
+impl LessonHandle {
+    /// Characters of the text started so far.
+    pub fn progress(&self) -> usize {
+        self.progress.load(Ordering::Relaxed)
+    }
+
+    /// Plays the rest of the text with `audio`, from the next character.
+    pub fn retime(&self, audio: MorseAudio) {
+        // nothing to do if the text has finished
+        let _ = self.retime.send(audio);
+    }
+}
+
+impl MorseSource {
+    pub fn new(audio: MorseAudio, text: impl Iterator<Item = char> + Send + 'static) -> Self {
+        Self {
+            audio,
+            text: Box::new(text),
+            symbols: VecDeque::new(),
+            map: morse::morse_map(),
+            buffer: Vec::new(),
+            pos: 0,
+            noise: None,
+            fading: None,
+            amplitude: 1.0,
+            progress: None,
+            retime: None,
+        }
+    }
+
+    pub fn with_amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    pub fn with_noise(mut self, config: NoiseConfig) -> Self {
+        self.noise = (!config.is_off())
+            .then(|| BandNoise::new(config, TONE_AMPLITUDE, self.audio.sample_rate));
+        self
+    }
+
+    pub fn with_fading(mut self, config: FadingConfig) -> Self {
+        self.fading = (!config.is_off()).then(|| Fading::new(config, self.audio.sample_rate));
+        self
+    }
+
+    /// Lets the app follow the text as it plays and change its timing.
+    pub fn with_handle(mut self) -> (Self, LessonHandle) {
+        let progress = Arc::new(AtomicUsize::new(0));
+        let (retime, receiver) = mpsc::channel();
+        self.progress = Some(progress.clone());
+        self.retime = Some(receiver);
+        (self, LessonHandle { progress, retime })
+    }
+
+    pub fn from_text(audio: MorseAudio, text: &str) -> Self {
+        Self::new(audio, text.chars().collect::<Vec<_>>().into_iter())
+    }
+
+    /// Renders the next known symbol into the buffer. Returns false once
+    /// the text runs out.
+    fn refill(&mut self) -> bool {
+        self.buffer.clear();
+        self.pos = 0;
+        while self.buffer.is_empty() {
+            let Some(symbol) = self.next_symbol() else {
+                return false;
+            };
+            if let Some(audio) = self.retime.as_ref().and_then(|r| r.try_iter().last()) {
+                self.audio = audio;
+            }
+            if let Some(progress) = &self.progress {
+                progress.fetch_add(symbol.chars().count(), Ordering::Relaxed);
             }
-            if out.len() >= self.gap1.len() {
-                out.truncate(out.len() - self.gap1.len());
-                out.extend(&self.gap3);
+            if let Some(code) = morse::encode_symbol(&symbol, &self.map) {
+                self.audio.write_code(&code, &mut self.buffer);
             }
         }
-        out
+        true
     }
 
-    pub fn to_source(&self, samples: Vec<f32>) -> SamplesBuffer {
-        SamplesBuffer::new(1, self.sample_rate as u32, samples)
+    fn next_symbol(&mut self) -> Option<String> {
+        if self.symbols.is_empty() {
+            // a prosign never spans whitespace, so a word at a time is enough
+            let mut word = String::new();
+            for ch in &mut self.text {
+                word.push(ch);
+                if ch.is_whitespace() {
+                    break;
+                }
+            }
+            self.symbols.extend(morse::symbols(&word));
+        }
+        self.symbols.pop_front()
     }
 }
 
-pub fn play_lesson_audio(
-    lesson_text: &str,
-    wpm: u32,
-    freq: f32,
-    effective_wpm: u32,
-) -> Result<(rodio::OutputStream, rodio::Sink), Box<dyn Error>> {
-    let sample_rate = 44_100;
-    let map = morse::morse_map();
+impl Iterator for MorseSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos >= self.buffer.len() && !self.refill() {
+            return None;
+        }
+        let mut sample = self.buffer[self.pos] * self.amplitude;
+        self.pos += 1;
+        if let Some(fading) = &mut self.fading {
+            sample *= fading.next_gain();
+        }
+        if let Some(noise) = &mut self.noise {
+            sample = (sample * noise.signal_gain() + noise.next_sample()).clamp(-1.0, 1.0);
+        }
+        Some(sample)
+    }
+}
+
+impl Source for MorseSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        1
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.audio.sample_rate as SampleRate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Several stations on the air at once: the target station the user copies,
+/// plus interfering stations on nearby frequencies (QRM).
+pub struct PileupSource {
+    target: MorseSource,
+    stations: Vec<MorseSource>,
+    gain: f32,
+}
+
+impl PileupSource {
+    pub fn new(target: MorseSource, stations: Vec<MorseSource>) -> Self {
+        let power: f32 = stations.iter().map(|s| s.amplitude * s.amplitude).sum();
+        Self {
+            target,
+            stations,
+            gain: 1.0 / (1.0 + power).sqrt(),
+        }
+    }
+}
+
+impl Iterator for PileupSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // the pileup lasts as long as the target is sending
+        let mut sample = self.target.next()?;
+        for station in &mut self.stations {
+            sample += station.next().unwrap_or(0.0);
+        }
+        Some((sample * self.gain).clamp(-1.0, 1.0))
+    }
+}
+
+impl Source for PileupSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        1
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.target.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// An endless tone that sounds while `key` is down, for sending practice.
+/// It ramps on and off over the keying envelope's rise time like a keyed
+/// element does.
+pub struct Sidetone {
+    key: Arc<AtomicBool>,
+    phase: f32,
+    phase_step: f32,
+    level: f32,
+    level_step: f32,
+    sample_rate: usize,
+}
 
-    let audio = MorseAudio::new(wpm, effective_wpm, freq, sample_rate);
+impl Sidetone {
+    pub fn new(key: Arc<AtomicBool>, freq: f32, rise_time_ms: f32, sample_rate: usize) -> Self {
+        let ramp = (rise_time_ms / 1000.0 * sample_rate as f32).max(1.0);
+        Self {
+            key,
+            phase: 0.0,
+            phase_step: std::f32::consts::TAU * freq / sample_rate as f32,
+            level: 0.0,
+            level_step: 1.0 / ramp,
+            sample_rate,
+        }
+    }
+}
 
-    let mut samples: Vec<f32> = Vec::new();
-    for ch in lesson_text.chars() {
-        if let Some(code) = map.get(&ch) {
-            samples.extend(audio.morse_to_audio(code));
+impl Iterator for Sidetone {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.key.load(Ordering::Relaxed) {
+            self.level = (self.level + self.level_step).min(1.0);
+        } else {
+            self.level = (self.level - self.level_step).max(0.0);
         }
+        let gain = 0.5 - 0.5 * (std::f32::consts::PI * self.level).cos();
+        let sample = TONE_AMPLITUDE * gain * self.phase.sin();
+        self.phase = (self.phase + self.phase_step) % std::f32::consts::TAU;
+        Some(sample)
+    }
+}
+
+impl Source for Sidetone {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        1
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate as SampleRate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// A station calling endlessly near the target's frequency, with its own
+/// pitch, speed, fist and signal strength.
+pub fn interfering_station(settings: &AudioSettings, sample_rate: usize) -> MorseSource {
+    let mut rng = rand::rng();
+    let offset: f32 = rng.random_range(80.0..400.0);
+    let tone_freq = if rng.random_bool(0.5) {
+        settings.tone_freq + offset
+    } else {
+        (settings.tone_freq - offset).max(200.0)
+    };
+    let wpm = (settings.wpm as i32 + rng.random_range(-5..=8)).max(5) as u32;
+    let fist = FistProfile::random().fist();
+    let audio = MorseAudio::new(wpm, wpm, tone_freq, settings.rise_time, fist, sample_rate);
+
+    // a few word gaps up front so the stations don't all start together
+    let lead_in = std::iter::repeat_n(' ', rng.random_range(0..4));
+    let calls = std::iter::repeat_with(lesson::calling_text)
+        .flat_map(|text| text.chars().collect::<Vec<_>>());
+    MorseSource::new(audio, lead_in.chain(calls)).with_amplitude(rng.random_range(0.3..1.0))
+}
+
+/// Builds the audio for a lesson with every effect in `settings` applied.
+/// Fails if the text contains anything that can't be sent.
+pub fn lesson_source(
+    text: &str,
+    settings: &AudioSettings,
+    sample_rate: usize,
+) -> Result<(Box<dyn Source + Send>, LessonHandle), UnknownSymbols> {
+    morse::check_text(text)?;
+    let audio = MorseAudio::from_settings(settings, sample_rate);
+    let (target, handle) = MorseSource::from_text(audio, text)
+        .with_fading(settings.fading)
+        .with_noise(settings.noise)
+        .with_handle();
+    if settings.qrm_stations == 0 {
+        return Ok((Box::new(target), handle));
     }
+    let stations = (0..settings.qrm_stations)
+        .map(|_| interfering_station(settings, sample_rate))
+        .collect();
+    Ok((Box::new(PileupSource::new(target, stations)), handle))
+}
+
+pub fn play_lesson_audio(
+    lesson_text: &str,
+    settings: &AudioSettings,
+) -> Result<(rodio::OutputStream, rodio::Sink, LessonHandle), Box<dyn Error>> {
+    let (source, handle) = lesson_source(lesson_text, settings, SAMPLE_RATE)?;
+    let mut stream = rodio::stream::OutputStreamBuilder::open_default_stream()?;
+    stream.log_on_drop(false);
+    let sink = Sink::connect_new(stream.mixer());
+    sink.set_volume(settings.volume);
+    sink.append(source);
+    Ok((stream, sink, handle))
+}
+
+/// Starts a sidetone that follows the returned key.
+pub fn play_sidetone(
+    settings: &AudioSettings,
+) -> Result<(rodio::OutputStream, rodio::Sink, Arc<AtomicBool>), Box<dyn Error>> {
+    let key = Arc::new(AtomicBool::new(false));
+    let mut stream = rodio::stream::OutputStreamBuilder::open_default_stream()?;
+    stream.log_on_drop(false);
+    let sink = Sink::connect_new(stream.mixer());
+    sink.set_volume(settings.volume);
+    sink.append(Sidetone::new(
+        key.clone(),
+        settings.tone_freq,
+        settings.rise_time,
+        SAMPLE_RATE,
+    ));
+    Ok((stream, sink, key))
+}
 
+/// Starts an iambic keyer sounding on the returned paddles.
+pub fn play_keyer(
+    settings: &AudioSettings,
+    keyer: KeyerConfig,
+) -> Result<(rodio::OutputStream, rodio::Sink, KeyerHandle), Box<dyn Error>> {
+    let wpm = keyer.wpm(settings.wpm);
+    let audio = MorseAudio::new(
+        wpm,
+        wpm,
+        settings.tone_freq,
+        settings.rise_time,
+        FistProfile::Keyer.fist(),
+        SAMPLE_RATE,
+    );
+    let (source, handle) = KeyerSource::new(audio, keyer.mode);
     let mut stream = rodio::stream::OutputStreamBuilder::open_default_stream()?;
     stream.log_on_drop(false);
-    let sink = Sink::connect_new(&stream.mixer());
-    let source = audio.to_source(samples);
+    let sink = Sink::connect_new(stream.mixer());
+    sink.set_volume(settings.volume);
     sink.append(source);
-    Ok((stream, sink))
+    Ok((stream, sink, handle))
 }
@@ -0,0 +1,205 @@
+use clap::ValueEnum;
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use serde::{Deserialize, Serialize};
+
+// RMS of the pink filter below when fed uniform noise in [-1, 1].
+const PINK_RMS: f32 = 1.74;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum NoiseKind {
+    #[default]
+    Off,
+    White,
+    Pink,
+}
+
+impl NoiseKind {
+    pub fn next(self) -> Self {
+        match self {
+            NoiseKind::Off => NoiseKind::White,
+            NoiseKind::White => NoiseKind::Pink,
+            NoiseKind::Pink => NoiseKind::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseConfig {
+    pub kind: NoiseKind,
+    pub snr_db: f32,
+    pub crashes_per_min: f32,
+}
+
+impl NoiseConfig {
+    pub fn is_off(&self) -> bool {
+        self.kind == NoiseKind::Off && self.crashes_per_min <= 0.0
+    }
+
+    pub fn describe(&self) -> String {
+        if self.is_off() {
+            return "off".to_string();
+        }
+        let mut text = format!("{:?} @ {} dB SNR", self.kind, self.snr_db);
+        if self.crashes_per_min > 0.0 {
+            text.push_str(&format!(", {}/min crashes", self.crashes_per_min));
+        }
+        text
+    }
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::Off,
+            snr_db: 10.0,
+            crashes_per_min: 0.0,
+        }
+    }
+}
+
+/// Band noise (QRN) mixed under a keyed signal: a white or pink noise floor
+/// plus random static crashes that decay away.
+pub struct BandNoise {
+    kind: NoiseKind,
+    rng: SmallRng,
+    signal_gain: f32,
+    noise_rms: f32,
+    pink: [f32; 7],
+    crash_prob: f32,
+    crash_level: f32,
+    crash_decay: f32,
+    crash_lowpass: f32,
+    sample_rate: usize,
+}
+
+impl BandNoise {
+    /// `signal_peak` is the peak amplitude of the clean tone; the signal is
+    /// scaled down by `signal_gain` to leave headroom for the noise.
+    pub fn new(config: NoiseConfig, signal_peak: f32, sample_rate: usize) -> Self {
+        // noise RMS relative to the tone's peak, from the tone power A^2 / 2
+        let relative_rms = (0.5 / 10f32.powf(config.snr_db / 10.0)).sqrt();
+        let signal_gain = 1.0 / (1.0 + 3.0 * relative_rms);
+        Self {
+            kind: config.kind,
+            rng: SmallRng::from_rng(&mut rand::rng()),
+            signal_gain,
+            noise_rms: signal_peak * signal_gain * relative_rms,
+            pink: [0.0; 7],
+            crash_prob: config.crashes_per_min / 60.0 / sample_rate as f32,
+            crash_level: 0.0,
+            crash_decay: 0.0,
+            crash_lowpass: 0.0,
+            sample_rate,
+        }
+    }
+
+    pub fn signal_gain(&self) -> f32 {
+        self.signal_gain
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let floor = match self.kind {
+            NoiseKind::Off => 0.0,
+            NoiseKind::White => self.rng.random_range(-1.0..1.0) * 3f32.sqrt(),
+            NoiseKind::Pink => self.next_pink() / PINK_RMS,
+        };
+        floor * self.noise_rms + self.next_crash()
+    }
+
+    // Paul Kellet's refined pink noise filter.
+    fn next_pink(&mut self) -> f32 {
+        let white: f32 = self.rng.random_range(-1.0..1.0);
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink
+    }
+
+    fn next_crash(&mut self) -> f32 {
+        if self.crash_prob > 0.0 && self.rng.random::<f32>() < self.crash_prob {
+            // a crash is a burst several times louder than the noise floor
+            // that dies away over tens of milliseconds
+            let decay_secs = self.rng.random_range(0.02..0.15);
+            self.crash_level = self.noise_rms.max(0.05) * self.rng.random_range(3.0..8.0);
+            self.crash_decay = (-1.0 / (decay_secs * self.sample_rate as f32)).exp();
+        }
+        if self.crash_level < 1e-4 {
+            return 0.0;
+        }
+        let burst = self.crash_level * self.rng.random_range(-1.0..1.0);
+        self.crash_level *= self.crash_decay;
+        self.crash_lowpass += 0.3 * (burst - self.crash_lowpass);
+        self.crash_lowpass
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FadingConfig {
+    /// how far the signal drops at the bottom of a fade, from 0 (none) to 1
+    pub depth: f32,
+    pub period_secs: f32,
+}
+
+impl FadingConfig {
+    pub fn is_off(&self) -> bool {
+        self.depth <= 0.0 || self.period_secs <= 0.0
+    }
+
+    pub fn describe(&self) -> String {
+        if self.is_off() {
+            "off".to_string()
+        } else {
+            format!(
+                "{:.0}% depth, {}s period",
+                self.depth.min(1.0) * 100.0,
+                self.period_secs
+            )
+        }
+    }
+}
+
+impl Default for FadingConfig {
+    fn default() -> Self {
+        Self {
+            depth: 0.0,
+            period_secs: 8.0,
+        }
+    }
+}
+
+/// Slow ionospheric fading (QSB) applied to a signal's amplitude. Two
+/// sinusoids with unrelated periods keep the fades from sounding regular.
+pub struct Fading {
+    depth: f32,
+    phase: [f32; 2],
+    step: [f32; 2],
+}
+
+impl Fading {
+    pub fn new(config: FadingConfig, sample_rate: usize) -> Self {
+        let mut rng = rand::rng();
+        let tau = std::f32::consts::TAU;
+        let base = tau / (config.period_secs * sample_rate as f32);
+        Self {
+            depth: config.depth.clamp(0.0, 1.0),
+            phase: [rng.random_range(0.0..tau), rng.random_range(0.0..tau)],
+            step: [base, base * 2.7],
+        }
+    }
+
+    pub fn next_gain(&mut self) -> f32 {
+        let wave = 0.7 * self.phase[0].cos() + 0.3 * self.phase[1].cos();
+        for (phase, step) in self.phase.iter_mut().zip(self.step) {
+            *phase = (*phase + step) % std::f32::consts::TAU;
+        }
+        1.0 - self.depth * (0.5 - 0.5 * wave)
+    }
+}
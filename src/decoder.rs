@@ -0,0 +1,384 @@
+use std::{collections::HashMap, error::Error};
+
+use crate::morse::{self, Alphabet};
+
+const MIN_TONE_FREQ: f32 = 200.0;
+const MAX_TONE_FREQ: f32 = 1500.0;
+// below twice the highest tone searched for, the tone can't be found
+const MIN_SAMPLE_RATE: usize = 2 * MAX_TONE_FREQ as usize;
+// the envelope detector steps by a hop and looks at a window of several
+// hops, trading a little timing resolution for a narrower, quieter filter
+const HOP_SECS: f32 = 0.004;
+const WINDOW_HOPS: usize = 4;
+// how quickly the detector's idea of the signal level falls during a fade
+const PEAK_DECAY_SECS: f32 = 1.0;
+
+/// Turns key-down and key-up durations into text, following the sender's
+/// speed as it drifts. Marks shorter than two dits are dits; gaps are
+/// measured against the element gap, which Farnsworth timing stretches
+/// separately from the marks.
+pub struct TimingDecoder {
+    dit: f32,
+    space_unit: f32,
+    last_space: Option<f32>,
+    word_done: bool,
+    code: String,
+    text: String,
+    map: HashMap<&'static str, String>,
+    marks: Vec<f32>,
+    gaps: Vec<f32>,
+    chars: Vec<KeyedChar>,
+}
+
+/// A decoded character and how it was keyed. Lengths are in dits at the
+/// speed the decoder was following at the time, so they stay comparable as
+/// the sender speeds up or slows down.
+pub struct KeyedChar {
+    pub text: String,
+    pub code: String,
+    pub marks: Vec<f32>,
+    /// gaps between the elements
+    pub gaps: Vec<f32>,
+    /// the gap after the character, once the next one starts
+    pub gap_after: Option<f32>,
+    pub word_after: bool,
+    pub wpm: f32,
+}
+
+impl TimingDecoder {
+    pub fn new(dit: f32, space_unit: f32, alphabet: Alphabet) -> Self {
+        Self {
+            dit,
+            space_unit,
+            last_space: None,
+            word_done: false,
+            code: String::new(),
+            text: String::new(),
+            map: morse::decode_map(alphabet),
+            marks: Vec::new(),
+            gaps: Vec::new(),
+            chars: Vec::new(),
+        }
+    }
+
+    pub fn from_wpm(wpm: u32, alphabet: Alphabet) -> Self {
+        let dit = 1.2 / wpm as f32;
+        Self::new(dit, dit, alphabet)
+    }
+
+    /// A key-down of `secs`.
+    pub fn mark(&mut self, secs: f32) {
+        if let Some(gap) = self.last_space.take() {
+            let units = gap / self.dit;
+            if gap < 2.0 * self.space_unit {
+                self.space_unit = adapt(self.space_unit, gap);
+                if !self.code.is_empty() {
+                    self.gaps.push(units);
+                }
+            } else {
+                if let Some(last) = self.chars.last_mut()
+                    && last.gap_after.is_none()
+                {
+                    last.gap_after = Some(units);
+                    last.word_after = gap >= 5.0 * self.space_unit;
+                }
+                if gap < 5.0 * self.space_unit {
+                    self.space_unit = adapt(self.space_unit, gap / 3.0);
+                }
+            }
+        }
+        self.word_done = false;
+        self.marks.push(secs / self.dit);
+        if secs < 2.0 * self.dit {
+            self.code.push('.');
+            self.dit = adapt(self.dit, secs);
+        } else {
+            self.code.push('-');
+            self.dit = adapt(self.dit, secs / 3.0);
+        }
+    }
+
+    /// A key-up of `secs`. While the key is still up this can be called again
+    /// with the growing length, so characters and word breaks show up live.
+    pub fn space(&mut self, secs: f32) {
+        self.last_space = Some(secs);
+        if secs >= 2.0 * self.space_unit {
+            self.finish_char();
+        }
+        if secs >= 5.0 * self.space_unit
+            && !self.word_done
+            && !self.text.is_empty()
+            && !self.text.ends_with(' ')
+        {
+            self.text.push(' ');
+            self.word_done = true;
+        }
+    }
+
+    /// Ends the transmission, decoding whatever is left.
+    pub fn finish(&mut self) {
+        self.finish_char();
+    }
+
+    fn finish_char(&mut self) {
+        if self.code.is_empty() {
+            return;
+        }
+        let text = match self.map.get(self.code.as_str()) {
+            Some(text) => text.clone(),
+            None => format!("[{}]", self.code),
+        };
+        self.text.push_str(&text);
+        self.chars.push(KeyedChar {
+            text,
+            code: std::mem::take(&mut self.code),
+            marks: std::mem::take(&mut self.marks),
+            gaps: std::mem::take(&mut self.gaps),
+            gap_after: None,
+            word_after: false,
+            wpm: self.wpm(),
+        });
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Every character decoded so far, with its timing.
+    pub fn chars(&self) -> &[KeyedChar] {
+        &self.chars
+    }
+
+    /// Elements of the character still being keyed.
+    pub fn pending(&self) -> &str {
+        &self.code
+    }
+
+    pub fn wpm(&self) -> f32 {
+        1.2 / self.dit
+    }
+}
+
+fn adapt(estimate: f32, sample: f32) -> f32 {
+    0.8 * estimate + 0.2 * sample
+}
+
+/// Power of `freq` in `samples`, by the Goertzel algorithm.
+pub fn goertzel_power(samples: &[f32], freq: f32, sample_rate: usize) -> f32 {
+    let coeff = 2.0 * (std::f32::consts::TAU * freq / sample_rate as f32).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for &x in samples {
+        let s0 = x + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
+
+/// Finds the CW tone's pitch by scanning the loudest parts of the recording.
+pub fn detect_tone(samples: &[f32], sample_rate: usize) -> f32 {
+    let block_len = sample_rate / 50;
+    let mut blocks: Vec<&[f32]> = samples.chunks(block_len).collect();
+    let energy = |b: &[f32]| b.iter().map(|x| x * x).sum::<f32>();
+    blocks.sort_by(|a, b| energy(b).total_cmp(&energy(a)));
+    blocks.truncate(100);
+
+    let mut best = (MIN_TONE_FREQ, 0.0);
+    let mut freq = MIN_TONE_FREQ;
+    while freq <= MAX_TONE_FREQ {
+        let power: f32 = blocks
+            .iter()
+            .map(|b| goertzel_power(b, freq, sample_rate))
+            .sum();
+        if power > best.1 {
+            best = (freq, power);
+        }
+        freq += 5.0;
+    }
+    best.0
+}
+
+/// Splits a recording into alternating key-down (`true`) and key-up runs,
+/// in seconds, starting with the first key-down. The threshold follows the
+/// signal's level so fading signals stay readable.
+pub fn key_runs(samples: &[f32], sample_rate: usize, freq: f32) -> Vec<(bool, f32)> {
+    let hop = ((HOP_SECS * sample_rate as f32) as usize).max(1);
+    let envelope: Vec<f32> = (0..samples.len().saturating_sub(WINDOW_HOPS * hop))
+        .step_by(hop)
+        .map(|i| goertzel_power(&samples[i..i + WINDOW_HOPS * hop], freq, sample_rate).sqrt())
+        .collect();
+    if envelope.is_empty() {
+        return Vec::new();
+    }
+
+    let mut sorted = envelope.clone();
+    sorted.sort_by(f32::total_cmp);
+    let floor = sorted[sorted.len() / 5];
+    let top = sorted[sorted.len() * 95 / 100];
+    let decay = (-HOP_SECS / PEAK_DECAY_SECS).exp();
+
+    let mut runs: Vec<(bool, f32)> = Vec::new();
+    let mut peak = top;
+    let mut on = false;
+    for &level in &envelope {
+        peak = level
+            .max(floor + (peak - floor) * decay)
+            .max(floor + 0.1 * (top - floor));
+        let range = peak - floor;
+        if on && level < floor + 0.4 * range {
+            on = false;
+        } else if !on && level > floor + 0.6 * range {
+            on = true;
+        }
+        match runs.last_mut() {
+            Some((state, len)) if *state == on => *len += HOP_SECS,
+            _ => runs.push((on, HOP_SECS)),
+        }
+    }
+
+    // fold glitches shorter than two hops into the runs around them
+    let mut cleaned: Vec<(bool, f32)> = Vec::new();
+    for (state, len) in runs {
+        match cleaned.last_mut() {
+            Some((last, last_len)) if *last == state => *last_len += len,
+            Some((_, last_len)) if len < 2.0 * HOP_SECS => *last_len += len,
+            _ => cleaned.push((state, len)),
+        }
+    }
+    let first_mark = cleaned
+        .iter()
+        .position(|(on, _)| *on)
+        .unwrap_or(cleaned.len());
+    cleaned.split_off(first_mark)
+}
+
+/// Starting estimates of the dit and the element gap, from the whole
+/// recording: the shorter of two clusters of mark lengths, and the lower
+/// quartile of the gaps (most gaps are the ones between elements).
+pub fn initial_timing(runs: &[(bool, f32)]) -> Option<(f32, f32)> {
+    let marks: Vec<f32> = runs.iter().filter(|r| r.0).map(|r| r.1).collect();
+    let mut spaces: Vec<f32> = runs.iter().filter(|r| !r.0).map(|r| r.1).collect();
+    let shortest = marks.iter().copied().reduce(f32::min)?;
+    let longest = marks.iter().copied().reduce(f32::max)?;
+
+    let (mut low, mut high) = (shortest, longest);
+    for _ in 0..10 {
+        let (short, long): (Vec<f32>, Vec<f32>) = marks.iter().partition(|&&m| m - low < high - m);
+        low = mean(&short).unwrap_or(low);
+        high = mean(&long).unwrap_or(high);
+    }
+    // with no clear dits and dahs, take everything to be dits
+    let dit = if high > 2.0 * low { low } else { mean(&marks)? };
+
+    spaces.sort_by(f32::total_cmp);
+    let space_unit = spaces.get(spaces.len() / 4).copied().unwrap_or(dit);
+    Some((dit, space_unit))
+}
+
+pub(crate) fn mean(values: &[f32]) -> Option<f32> {
+    (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+}
+
+pub struct AudioDecode {
+    pub text: String,
+    pub tone_freq: f32,
+    pub wpm: f32,
+}
+
+/// Decodes the CW in a recording. Fails if the sample rate is too low to
+/// carry a CW tone.
+pub fn decode_audio(
+    samples: &[f32],
+    sample_rate: usize,
+    alphabet: Alphabet,
+) -> Result<AudioDecode, Box<dyn Error>> {
+    if sample_rate < MIN_SAMPLE_RATE {
+        return Err(format!(
+            "sample rate of {} Hz is too low to decode, it must be at least {} Hz",
+            sample_rate, MIN_SAMPLE_RATE
+        )
+        .into());
+    }
+    let tone_freq = detect_tone(samples, sample_rate);
+    let runs = key_runs(samples, sample_rate, tone_freq);
+    let Some((dit, space_unit)) = initial_timing(&runs) else {
+        return Ok(AudioDecode {
+            text: String::new(),
+            tone_freq,
+            wpm: 0.0,
+        });
+    };
+
+    let mut decoder = TimingDecoder::new(dit, space_unit, alphabet);
+    for (on, secs) in runs {
+        if on {
+            decoder.mark(secs);
+        } else {
+            decoder.space(secs);
+        }
+    }
+    decoder.finish();
+    Ok(AudioDecode {
+        text: decoder.text().trim().to_string(),
+        tone_freq,
+        wpm: decoder.wpm(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::{self, AudioSettings},
+        band::{NoiseConfig, NoiseKind},
+    };
+
+    const TEXT: &str = "PARIS KMRSU 73 CQ DE W1AW";
+    const SAMPLE_RATE: usize = 8000;
+
+    fn round_trip(settings: AudioSettings) -> String {
+        let (source, _) = audio::lesson_source(TEXT, &settings, SAMPLE_RATE).unwrap();
+        let samples: Vec<f32> = source.collect();
+        decode_audio(&samples, SAMPLE_RATE, Alphabet::Latin)
+            .unwrap()
+            .text
+    }
+
+    #[test]
+    fn decodes_plain_timing() {
+        let settings = AudioSettings {
+            wpm: 20,
+            effective_wpm: 20,
+            ..AudioSettings::default()
+        };
+        assert_eq!(round_trip(settings), TEXT);
+    }
+
+    #[test]
+    fn decodes_farnsworth_timing() {
+        let settings = AudioSettings {
+            wpm: 20,
+            effective_wpm: 10,
+            ..AudioSettings::default()
+        };
+        assert_eq!(round_trip(settings), TEXT);
+    }
+
+    #[test]
+    fn decodes_through_noise() {
+        let settings = AudioSettings {
+            noise: NoiseConfig {
+                kind: NoiseKind::White,
+                snr_db: 10.0,
+                crashes_per_min: 0.0,
+            },
+            ..AudioSettings::default()
+        };
+        assert_eq!(round_trip(settings), TEXT);
+    }
+
+    #[test]
+    fn rejects_low_sample_rates() {
+        assert!(decode_audio(&[0.0; 100], 40, Alphabet::Latin).is_err());
+    }
+}
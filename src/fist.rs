@@ -0,0 +1,182 @@
+use clap::ValueEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::decoder::{KeyedChar, mean};
+
+/// How a sender's keying departs from machine-perfect timing.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fist {
+    /// random variation of every element and gap, as a fraction of its length
+    pub jitter: f32,
+    /// dah length in dits (3 is standard)
+    pub dah_ratio: f32,
+    /// 1 is standard; heavier keying lengthens marks and shortens the gaps
+    pub weight: f32,
+}
+
+impl Default for Fist {
+    fn default() -> Self {
+        FistProfile::Keyer.fist()
+    }
+}
+
+impl Fist {
+    pub fn is_perfect(&self) -> bool {
+        self.jitter <= 0.0 && self.dah_ratio == 3.0 && self.weight == 1.0
+    }
+
+    pub fn describe(&self) -> String {
+        if self.is_perfect() {
+            return "keyer".to_string();
+        }
+        format!(
+            "{:.0}% jitter, 1:{:.1}, weight {:.2}",
+            self.jitter * 100.0,
+            self.dah_ratio,
+            self.weight
+        )
+    }
+
+    /// Scales a nominal length by a random factor with a standard deviation of
+    /// `jitter`. The sum of two uniforms gives a cheap bell-shaped spread.
+    pub fn jittered(&self, len: f32, rng: &mut impl Rng) -> f32 {
+        if self.jitter <= 0.0 {
+            return len;
+        }
+        let spread = rng.random::<f32>() + rng.random::<f32>() - 1.0;
+        len * (1.0 + self.jitter * spread * 6f32.sqrt()).max(0.3)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum FistProfile {
+    /// machine-perfect timing
+    Keyer,
+    /// a good hand sender
+    Steady,
+    /// long dahs and heavy weighting
+    Heavy,
+    /// clipped dahs and light weighting
+    Light,
+    /// uneven timing all over
+    Sloppy,
+}
+
+impl FistProfile {
+    pub fn fist(self) -> Fist {
+        let (jitter, dah_ratio, weight) = match self {
+            FistProfile::Keyer => (0.0, 3.0, 1.0),
+            FistProfile::Steady => (0.05, 3.0, 1.0),
+            FistProfile::Heavy => (0.08, 3.4, 1.25),
+            FistProfile::Light => (0.08, 2.7, 0.85),
+            FistProfile::Sloppy => (0.2, 3.3, 1.1),
+        };
+        Fist {
+            jitter,
+            dah_ratio,
+            weight,
+        }
+    }
+
+    pub fn random() -> Self {
+        let profiles = FistProfile::value_variants();
+        profiles[rand::rng().random_range(0..profiles.len())]
+    }
+}
+
+/// How someone's sending compares with ideal PARIS timing, worked out from
+/// what the decoder heard. Lengths are in dits.
+pub struct FistReport {
+    pub dah_ratio: Option<f32>,
+    pub element_gap: Option<f32>,
+    pub char_gap: Option<f32>,
+    pub word_gap: Option<f32>,
+    pub start_wpm: f32,
+    pub end_wpm: f32,
+    /// each character sent, worst timed first
+    pub chars: Vec<CharTiming>,
+}
+
+pub struct CharTiming {
+    pub text: String,
+    pub count: usize,
+    /// mean deviation of the elements and gaps from their ideal lengths, as a
+    /// fraction of those lengths
+    pub error: f32,
+}
+
+impl FistReport {
+    pub fn analyze(chars: &[KeyedChar]) -> Option<Self> {
+        let first = chars.first()?;
+        let last = chars.last()?;
+
+        let mut dits = Vec::new();
+        let mut dahs = Vec::new();
+        for c in chars {
+            for (element, &len) in c.code.chars().zip(&c.marks) {
+                if element == '-' {
+                    dahs.push(len);
+                } else {
+                    dits.push(len);
+                }
+            }
+        }
+        let element_gaps: Vec<f32> = chars.iter().flat_map(|c| c.gaps.iter().copied()).collect();
+        let (word_gaps, char_gaps): (Vec<&KeyedChar>, Vec<&KeyedChar>) = chars
+            .iter()
+            .filter(|c| c.gap_after.is_some())
+            .partition(|c| c.word_after);
+        let gap_after =
+            |cs: Vec<&KeyedChar>| mean(&cs.iter().filter_map(|c| c.gap_after).collect::<Vec<_>>());
+
+        let mut per_char: Vec<CharTiming> = Vec::new();
+        for c in chars {
+            let error = char_error(c);
+            match per_char.iter_mut().find(|t| t.text == c.text) {
+                Some(timing) => {
+                    timing.error =
+                        (timing.error * timing.count as f32 + error) / (timing.count + 1) as f32;
+                    timing.count += 1;
+                }
+                None => per_char.push(CharTiming {
+                    text: c.text.clone(),
+                    count: 1,
+                    error,
+                }),
+            }
+        }
+        per_char.sort_by(|a, b| b.error.total_cmp(&a.error));
+
+        // speed at each end, from the first and last thirds of the characters
+        let third = (chars.len() / 3).max(1);
+        let wpm = |cs: &[KeyedChar]| mean(&cs.iter().map(|c| c.wpm).collect::<Vec<_>>());
+
+        Some(Self {
+            dah_ratio: mean(&dahs).zip(mean(&dits)).map(|(dah, dit)| dah / dit),
+            element_gap: mean(&element_gaps),
+            char_gap: gap_after(char_gaps),
+            word_gap: gap_after(word_gaps),
+            start_wpm: wpm(&chars[..third]).unwrap_or(first.wpm),
+            end_wpm: wpm(&chars[chars.len() - third..]).unwrap_or(last.wpm),
+            chars: per_char,
+        })
+    }
+}
+
+fn char_error(c: &KeyedChar) -> f32 {
+    let marks = c.code.chars().zip(&c.marks).map(|(element, &len)| {
+        if element == '-' {
+            (len, 3.0)
+        } else {
+            (len, 1.0)
+        }
+    });
+    let gaps = c.gaps.iter().map(|&len| (len, 1.0));
+    let errors: Vec<f32> = marks
+        .chain(gaps)
+        .map(|(len, ideal)| (len - ideal).abs() / ideal)
+        .collect();
+    mean(&errors).unwrap_or(0.0)
+}
@@ -0,0 +1,327 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{Receiver, Sender, channel},
+    },
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use rodio::{ChannelCount, SampleRate, Source};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::MorseAudio;
+
+/// What the keyer does when a squeeze is let go part way through an element.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum IambicMode {
+    /// finish the element and stop
+    A,
+    /// finish the element and send one more of the other kind
+    #[default]
+    B,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyerConfig {
+    /// follows the character speed when not set
+    pub wpm: Option<u32>,
+    pub mode: IambicMode,
+}
+
+impl KeyerConfig {
+    pub fn wpm(&self, char_wpm: u32) -> u32 {
+        self.wpm.unwrap_or(char_wpm)
+    }
+}
+
+/// The dit and dah paddles, shared between the UI thread that reads the
+/// keyboard and the keyer that runs in the audio thread. A press is latched as
+/// well as held, so a tap shorter than one sample buffer still counts.
+#[derive(Default)]
+pub struct Paddles {
+    dit: AtomicBool,
+    dah: AtomicBool,
+    dit_pressed: AtomicBool,
+    dah_pressed: AtomicBool,
+}
+
+impl Paddles {
+    pub fn set(&self, dah: bool, down: bool) {
+        let (held, pressed) = if dah {
+            (&self.dah, &self.dah_pressed)
+        } else {
+            (&self.dit, &self.dit_pressed)
+        };
+        held.store(down, Ordering::Relaxed);
+        if down {
+            pressed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// One element sent by the keyer, with the gap before it, in seconds.
+pub struct KeyedElement {
+    pub gap: f32,
+    pub mark: f32,
+}
+
+/// The UI thread's side of a running keyer.
+pub struct KeyerHandle {
+    pub paddles: Arc<Paddles>,
+    pub elements: Receiver<KeyedElement>,
+    idle: Arc<AtomicUsize>,
+    sample_rate: usize,
+}
+
+impl KeyerHandle {
+    /// How long the keyer has been idle since its last element, in seconds.
+    pub fn idle(&self) -> f32 {
+        self.idle.load(Ordering::Relaxed) as f32 / self.sample_rate as f32
+    }
+}
+
+/// An iambic keyer in the style of the Curtis chips. Holding one paddle sends
+/// a string of that element, squeezing both alternates them, and a paddle
+/// pressed during an element is remembered and sent next. Elements are timed
+/// in samples and sound through the same keyed tones as `MorseAudio`.
+pub struct KeyerSource {
+    audio: MorseAudio,
+    mode: IambicMode,
+    paddles: Arc<Paddles>,
+    elements: Sender<KeyedElement>,
+    idle: Arc<AtomicUsize>,
+    gap_samples: usize,
+    /// the element being sent (`true` for a dah) and how far into it we are
+    current: Option<(bool, usize)>,
+    last: Option<bool>,
+    dit_memory: bool,
+    dah_memory: bool,
+    squeezed: bool,
+}
+
+impl KeyerSource {
+    pub fn new(audio: MorseAudio, mode: IambicMode) -> (Self, KeyerHandle) {
+        let paddles = Arc::new(Paddles::default());
+        let idle = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = channel();
+        let handle = KeyerHandle {
+            paddles: paddles.clone(),
+            elements: receiver,
+            idle: idle.clone(),
+            sample_rate: audio.sample_rate,
+        };
+        let source = Self {
+            audio,
+            mode,
+            paddles,
+            elements: sender,
+            idle,
+            gap_samples: 0,
+            current: None,
+            last: None,
+            dit_memory: false,
+            dah_memory: false,
+            squeezed: false,
+        };
+        (source, handle)
+    }
+
+    fn next_element(&mut self) -> Option<bool> {
+        let dit_held = self.paddles.dit.load(Ordering::Relaxed);
+        let dah_held = self.paddles.dah.load(Ordering::Relaxed);
+        // mode B completes a squeeze with the other element even once released
+        let finish_squeeze = self.mode == IambicMode::B && self.squeezed;
+        let dit = dit_held || self.dit_memory || (finish_squeeze && self.last == Some(true));
+        let dah = dah_held || self.dah_memory || (finish_squeeze && self.last == Some(false));
+        let next = match (dit, dah) {
+            // a squeeze alternates, starting with a dit
+            (true, true) => Some(self.last == Some(false)),
+            (true, false) => Some(false),
+            (false, true) => Some(true),
+            (false, false) => None,
+        };
+        if let Some(dah) = next {
+            // sending an element uses up its memory, and a paddle still held
+            // is seen as held; memory is only for a press during the element
+            self.dit_memory &= dah && !dit_held;
+            self.dah_memory &= !dah && !dah_held;
+        }
+        self.squeezed = false;
+        next
+    }
+}
+
+impl Iterator for KeyerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.dit_memory |= self.paddles.dit_pressed.swap(false, Ordering::Relaxed);
+        self.dah_memory |= self.paddles.dah_pressed.swap(false, Ordering::Relaxed);
+
+        if self.current.is_none() {
+            match self.next_element() {
+                Some(dah) => self.current = Some((dah, 0)),
+                None => {
+                    self.gap_samples += 1;
+                    self.idle.store(self.gap_samples, Ordering::Relaxed);
+                    return Some(0.0);
+                }
+            }
+        }
+
+        let (dah, pos) = self.current.as_mut()?;
+        let dah = *dah;
+        if self.paddles.dit.load(Ordering::Relaxed) && self.paddles.dah.load(Ordering::Relaxed) {
+            self.squeezed = true;
+        }
+        let mark = if dah {
+            &self.audio.dah
+        } else {
+            &self.audio.dit
+        };
+        let gap = (self.audio.gap1_len * self.audio.sample_rate as f32) as usize;
+        let sample = mark.get(*pos).copied().unwrap_or(0.0);
+        if *pos == 0 {
+            self.idle.store(0, Ordering::Relaxed);
+        }
+        *pos += 1;
+
+        if *pos == mark.len() {
+            let rate = self.audio.sample_rate as f32;
+            // the UI may have gone away; the keyer just keeps sounding
+            let _ = self.elements.send(KeyedElement {
+                gap: self.gap_samples as f32 / rate,
+                mark: mark.len() as f32 / rate,
+            });
+            self.gap_samples = 0;
+        } else if *pos >= mark.len() {
+            self.gap_samples += 1;
+            self.idle.store(self.gap_samples, Ordering::Relaxed);
+        }
+        if *pos >= mark.len() + gap {
+            self.current = None;
+            self.last = Some(dah);
+        }
+        Some(sample)
+    }
+}
+
+impl Source for KeyerSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        1
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.audio.sample_rate as SampleRate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fist::FistProfile;
+
+    // 12 wpm at 1 kHz: a dit and the gap after it are 100 samples each
+    fn keyer(mode: IambicMode) -> (KeyerSource, KeyerHandle) {
+        let audio = MorseAudio::new(12, 12, 600.0, 5.0, FistProfile::Keyer.fist(), 1000);
+        KeyerSource::new(audio, mode)
+    }
+
+    fn run(source: &mut KeyerSource, samples: usize) {
+        for _ in 0..samples {
+            source.next();
+        }
+    }
+
+    fn sent(handle: &KeyerHandle) -> String {
+        handle
+            .elements
+            .try_iter()
+            .map(|element| if element.mark > 0.2 { '-' } else { '.' })
+            .collect()
+    }
+
+    #[test]
+    fn holding_a_paddle_repeats_its_element() {
+        let (mut source, handle) = keyer(IambicMode::B);
+        handle.paddles.set(false, true);
+        run(&mut source, 1000);
+        handle.paddles.set(false, false);
+        run(&mut source, 1000);
+        assert_eq!(sent(&handle), ".....");
+
+        handle.paddles.set(true, true);
+        run(&mut source, 1200);
+        handle.paddles.set(true, false);
+        run(&mut source, 1000);
+        assert_eq!(sent(&handle), "---");
+    }
+
+    #[test]
+    fn a_squeeze_alternates_starting_with_a_dit() {
+        let (mut source, handle) = keyer(IambicMode::B);
+        handle.paddles.set(false, true);
+        handle.paddles.set(true, true);
+        run(&mut source, 1200);
+        assert_eq!(sent(&handle), ".-.-");
+    }
+
+    #[test]
+    fn a_tap_during_an_element_is_remembered() {
+        let (mut source, handle) = keyer(IambicMode::A);
+        handle.paddles.set(false, true);
+        run(&mut source, 50);
+        handle.paddles.set(true, true);
+        handle.paddles.set(true, false);
+        run(&mut source, 50);
+        handle.paddles.set(false, false);
+        run(&mut source, 1000);
+        assert_eq!(sent(&handle), ".-");
+    }
+
+    fn release_squeeze_during_first_dit(mode: IambicMode) -> String {
+        let (mut source, handle) = keyer(mode);
+        handle.paddles.set(false, true);
+        handle.paddles.set(true, true);
+        run(&mut source, 50);
+        handle.paddles.set(false, false);
+        handle.paddles.set(true, false);
+        run(&mut source, 1000);
+        sent(&handle)
+    }
+
+    #[test]
+    fn releasing_during_the_dah_of_a_squeeze() {
+        for (mode, expected) in [(IambicMode::A, ".-"), (IambicMode::B, ".-.")] {
+            let (mut source, handle) = keyer(mode);
+            handle.paddles.set(false, true);
+            handle.paddles.set(true, true);
+            run(&mut source, 400);
+            handle.paddles.set(false, false);
+            handle.paddles.set(true, false);
+            run(&mut source, 1000);
+            assert_eq!(sent(&handle), expected, "mode {:?}", mode);
+        }
+    }
+
+    #[test]
+    fn mode_a_stops_when_a_squeeze_is_released() {
+        assert_eq!(release_squeeze_during_first_dit(IambicMode::A), ".");
+    }
+
+    #[test]
+    fn mode_b_sends_one_more_element_after_a_squeeze() {
+        assert_eq!(release_squeeze_during_first_dit(IambicMode::B), ".-");
+    }
+}
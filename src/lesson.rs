@@ -1,33 +1,277 @@
-use crate::morse::KOCH_SEQUENCE;
+use std::{fmt, str::FromStr};
+
+use crate::{morse::Alphabet, scores::ScoreData};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// keeps a timed session finite whatever the timing says
+const MAX_GROUPS: usize = 1000;
+// how much more often the newest letter comes up than an old one
+const NEWEST_WEIGHT: f32 = 1.0;
+// how much more often a letter comes up if it was missed every time lately
+const MISS_WEIGHT: f32 = 4.0;
+
+/// How many characters go in each group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum GroupSize {
+    Fixed(usize),
+    /// anywhere from the first to the second, inclusive
+    Range(usize, usize),
+    /// anywhere from 1 to 7, like plain-language words
+    Random,
+}
+
+impl GroupSize {
+    fn pick(self, rng: &mut impl Rng) -> usize {
+        match self {
+            GroupSize::Fixed(size) => size,
+            GroupSize::Range(min, max) => rng.random_range(min..=max),
+            GroupSize::Random => rng.random_range(1..=7),
+        }
+    }
+}
+
+impl fmt::Display for GroupSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupSize::Fixed(size) => write!(f, "{}", size),
+            GroupSize::Range(min, max) => write!(f, "{}-{}", min, max),
+            GroupSize::Random => write!(f, "random"),
+        }
+    }
+}
+
+impl FromStr for GroupSize {
+    type Err = String;
+
+    /// Parses `5`, `3-7` or `random`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = |n: &str| match n.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(format!("invalid group size `{}`", s)),
+        };
+        if s.trim().eq_ignore_ascii_case("random") {
+            Ok(GroupSize::Random)
+        } else if let Some((min, max)) = s.split_once('-') {
+            let (min, max) = (size(min)?, size(max)?);
+            if min > max {
+                return Err(format!("group size range `{}` is backwards", s));
+            }
+            Ok(GroupSize::Range(min, max))
+        } else {
+            Ok(GroupSize::Fixed(size(s)?))
+        }
+    }
+}
+
+impl TryFrom<String> for GroupSize {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<GroupSize> for String {
+    fn from(size: GroupSize) -> Self {
+        size.to_string()
+    }
+}
+
+/// How long a lesson runs: a set number of groups, or as many as fill the
+/// time at the current speed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", try_from = "UncheckedLength")]
+pub enum SessionLength {
+    Groups(usize),
+    Minutes(f32),
+}
+
+/// A session length as written in the settings file, before it is checked.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum UncheckedLength {
+    Groups(usize),
+    Minutes(f32),
+}
+
+impl TryFrom<UncheckedLength> for SessionLength {
+    type Error = String;
+
+    fn try_from(length: UncheckedLength) -> Result<Self, Self::Error> {
+        match length {
+            UncheckedLength::Groups(0) => Err("a lesson needs at least one group".to_string()),
+            UncheckedLength::Groups(n) => Ok(SessionLength::Groups(n)),
+            UncheckedLength::Minutes(m) => check_minutes(m).map(SessionLength::Minutes),
+        }
+    }
+}
+
+/// Parses a lesson length in minutes, which has to be a positive number.
+pub fn parse_minutes(s: &str) -> Result<f32, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("invalid number of minutes `{}`", s))
+        .and_then(check_minutes)
+}
+
+fn check_minutes(minutes: f32) -> Result<f32, String> {
+    if minutes > 0.0 && minutes.is_finite() {
+        Ok(minutes)
+    } else {
+        Err(format!("a lesson can't last {} minutes", minutes))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LessonConfig {
+    pub group_size: GroupSize,
+    pub length: SessionLength,
+}
 
-pub fn lesson_text(current_lesson: usize) -> String {
+impl LessonConfig {
+    pub fn describe(&self) -> String {
+        let size = match self.group_size {
+            GroupSize::Random => "random".to_string(),
+            size => format!("{}-char", size),
+        };
+        match self.length {
+            SessionLength::Groups(n) => format!("{} {} groups", n, size),
+            SessionLength::Minutes(m) => format!("{} min of {} groups", m, size),
+        }
+    }
+}
+
+impl Default for LessonConfig {
+    fn default() -> Self {
+        Self {
+            group_size: GroupSize::Fixed(5),
+            length: SessionLength::Groups(10),
+        }
+    }
+}
+
+/// Random groups of the lesson's letters, favouring the newest one and any
+/// the student has been missing lately. `duration` gives how long a piece of
+/// text takes to send, for sessions measured in minutes.
+pub fn lesson_text(
+    current_lesson: usize,
+    alphabet: Alphabet,
+    config: &LessonConfig,
+    scores: &ScoreData,
+    duration: impl Fn(&str) -> f32,
+) -> String {
     let count = if current_lesson == 1 {
         2
     } else {
         current_lesson + 1
     };
-    let letters = &KOCH_SEQUENCE[..count];
+    let letters: Vec<String> = alphabet.koch_sequence()[..count]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    let weights: Vec<f32> = letters
+        .iter()
+        .enumerate()
+        .map(|(i, letter)| {
+            // both of lesson 1's letters are new, so neither is favoured
+            let newest = if current_lesson > 1 && i == count - 1 {
+                NEWEST_WEIGHT
+            } else {
+                0.0
+            };
+            1.0 + newest + MISS_WEIGHT * scores.recent_miss(letter)
+        })
+        .collect();
+    session_text(&letters, &weights, config, duration)
+}
+
+/// Random groups mixing just the given symbols, to drill a pair that keeps
+/// getting mixed up.
+pub fn drill_text(
+    symbols: &[String],
+    config: &LessonConfig,
+    duration: impl Fn(&str) -> f32,
+) -> String {
+    session_text(symbols, &vec![1.0; symbols.len()], config, duration)
+}
+
+fn session_text(
+    symbols: &[String],
+    weights: &[f32],
+    config: &LessonConfig,
+    duration: impl Fn(&str) -> f32,
+) -> String {
     let mut rng = rand::rng();
+    let mut groups: Vec<String> = Vec::new();
+    let mut secs = 0.0;
+    loop {
+        let done = match config.length {
+            SessionLength::Groups(n) => groups.len() >= n,
+            SessionLength::Minutes(m) => secs >= m * 60.0 || groups.len() >= MAX_GROUPS,
+        };
+        if done {
+            break;
+        }
+        let group = random_group(symbols, weights, config.group_size.pick(&mut rng), &mut rng);
+        secs += duration(&format!("{} ", group));
+        groups.push(group);
+    }
 
-    let words: Vec<String> = (0..10)
-        .map(|_| {
-            (0..5)
-                .map(|_| {
-                    let idx = rng.random_range(0..letters.len());
-                    letters[idx]
-                })
-                .collect()
-        })
+    groups.join(" ")
+}
+
+/// A shorter text to send with the key, drawn from the same letters as the
+/// receiving lesson.
+pub fn sending_text(current_lesson: usize, alphabet: Alphabet) -> String {
+    let count = if current_lesson == 1 {
+        2
+    } else {
+        current_lesson + 1
+    };
+    let letters: Vec<String> = alphabet.koch_sequence()[..count]
+        .iter()
+        .map(|c| c.to_string())
         .collect();
+    let weights = vec![1.0; letters.len()];
+    let mut rng = rand::rng();
 
+    let words: Vec<String> = (0..4)
+        .map(|_| random_group(&letters, &weights, 5, &mut rng))
+        .collect();
     words.join(" ")
 }
 
-pub fn practice_text(lesson_num: usize) -> String {
+/// A group of `symbols` picked in proportion to `weights`. Symbols may be
+/// prosigns, which count as one character.
+fn random_group(
+    symbols: &[String],
+    weights: &[f32],
+    group_size: usize,
+    rng: &mut impl Rng,
+) -> String {
+    let total: f32 = weights.iter().sum();
+    (0..group_size)
+        .map(|_| {
+            let mut x = rng.random_range(0.0..total);
+            for (symbol, &weight) in symbols.iter().zip(weights) {
+                if x < weight {
+                    return symbol.as_str();
+                }
+                x -= weight;
+            }
+            symbols[symbols.len() - 1].as_str()
+        })
+        .collect()
+}
+
+pub fn practice_text(lesson_num: usize, alphabet: Alphabet) -> String {
+    let koch = alphabet.koch_sequence();
     if lesson_num == 1 {
         // Lesson 1 introduces K and M
-        let letters = &KOCH_SEQUENCE[0..2];
+        let letters = &koch[0..2];
         letters
             .iter()
             .map(|c| c.to_string())
@@ -37,18 +281,44 @@ pub fn practice_text(lesson_num: usize) -> String {
             .join(" ")
     } else {
         // Subsequent lessons: just the newly introduced letter
-        let new_letter = KOCH_SEQUENCE[lesson_num];
-        std::iter::repeat(new_letter.to_string())
-            .take(20)
+        let new_letter = koch[lesson_num];
+        std::iter::repeat_n(new_letter.to_string(), 20)
             .collect::<Vec<_>>()
             .join(" ")
     }
 }
 
-pub fn new_letters_for_lesson<'a>(lesson_num: usize) -> &'a [char] {
+pub fn new_letters_for_lesson(lesson_num: usize, alphabet: Alphabet) -> &'static [char] {
+    let koch = alphabet.koch_sequence();
     if lesson_num == 1 {
-        &KOCH_SEQUENCE[0..2]
+        &koch[0..2]
     } else {
-        &KOCH_SEQUENCE[lesson_num..lesson_num + 1]
+        &koch[lesson_num..lesson_num + 1]
+    }
+}
+
+const CALL_PREFIXES: &[&str] = &[
+    "K", "W", "N", "AA", "KB", "VE", "G", "M", "DL", "F", "I", "EA", "ON", "PA", "SM", "OH", "OK",
+    "SP", "UA", "JA", "VK", "ZL", "LU", "PY",
+];
+
+pub fn random_callsign() -> String {
+    let mut rng = rand::rng();
+    let prefix = CALL_PREFIXES[rng.random_range(0..CALL_PREFIXES.len())];
+    let digit = rng.random_range(0..10);
+    let suffix: String = (0..rng.random_range(1..=3))
+        .map(|_| rng.random_range(b'A'..=b'Z') as char)
+        .collect();
+    format!("{}{}{}", prefix, digit, suffix)
+}
+
+/// What an interfering station sends: its callsign, the way stations call
+/// into a pileup.
+pub fn calling_text() -> String {
+    let call = random_callsign();
+    match rand::rng().random_range(0..3) {
+        0 => format!("{} ", call),
+        1 => format!("{} {} ", call, call),
+        _ => format!("DE {} {} K ", call, call),
     }
 }
@@ -1,35 +1,309 @@
+mod align;
 mod app;
 mod audio;
+mod band;
+mod decoder;
+mod fist;
+mod keyer;
 mod lesson;
 mod morse;
 mod scores;
+mod sending;
+mod settings;
 mod ui;
+mod wav;
 
 use app::App;
-use clap::Parser;
+use audio::AudioSettings;
+use band::{FadingConfig, NoiseConfig, NoiseKind};
+use clap::{ArgGroup, Parser, Subcommand};
+use fist::{Fist, FistProfile};
+use keyer::{IambicMode, KeyerConfig};
+use lesson::{GroupSize, LessonConfig, SessionLength};
+use morse::Alphabet;
+use settings::Settings;
 
-use std::error::Error;
+use std::{error::Error, io, path::PathBuf, process::ExitCode};
 
+// Options left out fall back to the saved settings; `--save` makes the ones
+// given the new saved settings.
 #[derive(Parser, Debug)]
 struct Args {
-    /// character speed
-    #[arg(short, long, default_value_t = 20)]
-    wpm: u32,
+    /// character speed [saved default: 20]
+    #[arg(short, long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    wpm: Option<u32>,
 
-    /// effective overall wpm
-    #[arg(long, default_value_t = 15)]
-    effective_wpm: u32,
+    /// effective overall wpm [saved default: 15]
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    effective_wpm: Option<u32>,
 
-    /// tone frequency (Hz)
-    #[arg(short, long, default_value_t = 600.0)]
-    tone_freq: f32,
+    /// tone frequency (Hz) [saved default: 600]
+    #[arg(short, long, global = true)]
+    tone_freq: Option<f32>,
+
+    /// keying envelope rise/fall time (ms) [saved default: 5]
+    #[arg(long, global = true)]
+    rise_time: Option<f32>,
+
+    /// band noise mixed under the signal [saved default: off]
+    #[arg(long, value_enum, global = true)]
+    noise: Option<NoiseKind>,
+
+    /// signal-to-noise ratio of the band noise (dB) [saved default: 10]
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    snr: Option<f32>,
+
+    /// static crashes per minute [saved default: 0]
+    #[arg(long, global = true)]
+    crashes: Option<f32>,
+
+    /// QSB fading depth, from 0 (off) to 1 (fades out completely) [saved default: 0]
+    #[arg(long, global = true)]
+    qsb_depth: Option<f32>,
+
+    /// QSB fading period (seconds) [saved default: 8]
+    #[arg(long, global = true)]
+    qsb_period: Option<f32>,
+
+    /// sender's fist: how far the timing is from machine-perfect [saved default: keyer]
+    #[arg(long, value_enum, global = true)]
+    fist: Option<FistProfile>,
+
+    /// random timing jitter as a fraction of each element (overrides --fist)
+    #[arg(long, global = true)]
+    jitter: Option<f32>,
+
+    /// dah:dit ratio (overrides --fist)
+    #[arg(long, global = true)]
+    dah_ratio: Option<f32>,
+
+    /// keying weight, 1 is standard (overrides --fist)
+    #[arg(long, global = true)]
+    weight: Option<f32>,
+
+    /// code table for lessons, typing and decoding [saved default: latin]
+    #[arg(long, value_enum, global = true)]
+    alphabet: Option<Alphabet>,
+
+    /// number of interfering stations calling around the target (QRM) [saved default: 0]
+    #[arg(long, global = true)]
+    qrm: Option<usize>,
+
+    /// iambic keyer mode for sending with paddles [saved default: b]
+    #[arg(long, value_enum, global = true)]
+    keyer_mode: Option<IambicMode>,
+
+    /// iambic keyer speed [saved default: the character speed]
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    keyer_wpm: Option<u32>,
+
+    /// characters per group: a number (5), a range (3-7) or `random`
+    #[arg(long, global = true)]
+    group_size: Option<GroupSize>,
+
+    /// groups per lesson
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "minutes",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    groups: Option<usize>,
+
+    /// lesson length in minutes, instead of a number of groups
+    #[arg(long, global = true, value_parser = lesson::parse_minutes)]
+    minutes: Option<f32>,
+
+    /// save the options given as the new defaults
+    #[arg(long, global = true)]
+    save: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// render a lesson, letter practice or custom text to a WAV file
+    Export(ExportArgs),
+
+    /// turn dot/dash text (`-.- --`, words split by `/`) back into text
+    Decode {
+        /// codes to decode; read from stdin if none are given
+        #[arg(allow_hyphen_values = true)]
+        code: Vec<String>,
+    },
+
+    /// decode the CW in a WAV recording
+    DecodeAudio {
+        /// WAV file to decode
+        file: PathBuf,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group(ArgGroup::new("source").required(true)))]
+struct ExportArgs {
+    /// output WAV file
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// random groups for this Koch lesson
+    #[arg(short, long, group = "source")]
+    lesson: Option<usize>,
+
+    /// new letter(s) of this Koch lesson on repeat
+    #[arg(short, long, group = "source")]
+    practice: Option<usize>,
+
+    /// arbitrary text to send
+    #[arg(long, group = "source")]
+    text: Option<String>,
+
+    /// sample rate of the WAV file (Hz)
+    #[arg(
+        long,
+        default_value_t = audio::SAMPLE_RATE,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(8000..)
+    )]
+    sample_rate: usize,
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            let mut source = err.source();
+            while let Some(cause) = source {
+                eprintln!("  {}", cause);
+                source = cause.source();
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let settings = args.apply(Settings::load()?);
+    if args.save {
+        settings.store()?;
+    }
+
+    match &args.command {
+        Some(Command::Export(export_args)) => export(&settings, export_args)?,
+        Some(Command::Decode { code }) => {
+            let code_text = if code.is_empty() {
+                io::read_to_string(io::stdin())?
+            } else {
+                code.join(" ")
+            };
+            println!("{}", morse::decode(&code_text, settings.alphabet));
+        }
+        Some(Command::DecodeAudio { file }) => {
+            let (samples, sample_rate) = wav::read_wav(file)?;
+            let decoded = decoder::decode_audio(&samples, sample_rate, settings.alphabet)?;
+            eprintln!(
+                "tone {:.0} Hz, about {:.0} wpm",
+                decoded.tone_freq, decoded.wpm
+            );
+            println!("{}", decoded.text);
+        }
+        None => {
+            let mut app = App::new(settings)?;
+            app.run()?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Args {
+    /// The saved settings with the options given on the command line on top.
+    fn apply(&self, saved: Settings) -> Settings {
+        let audio = saved.audio;
+        let wpm = self.wpm.unwrap_or(audio.wpm);
+        Settings {
+            audio: AudioSettings {
+                wpm,
+                effective_wpm: self.effective_wpm.unwrap_or(audio.effective_wpm),
+                tone_freq: self.tone_freq.unwrap_or(audio.tone_freq),
+                rise_time: self.rise_time.unwrap_or(audio.rise_time),
+                noise: NoiseConfig {
+                    kind: self.noise.unwrap_or(audio.noise.kind),
+                    snr_db: self.snr.unwrap_or(audio.noise.snr_db),
+                    crashes_per_min: self.crashes.unwrap_or(audio.noise.crashes_per_min),
+                },
+                fading: FadingConfig {
+                    depth: self.qsb_depth.unwrap_or(audio.fading.depth),
+                    period_secs: self.qsb_period.unwrap_or(audio.fading.period_secs),
+                },
+                fist: self.fist_config(audio.fist),
+                qrm_stations: self.qrm.unwrap_or(audio.qrm_stations),
+                volume: audio.volume,
+            },
+            keyer: KeyerConfig {
+                wpm: self.keyer_wpm.or(saved.keyer.wpm),
+                mode: self.keyer_mode.unwrap_or(saved.keyer.mode),
+            },
+            alphabet: self.alphabet.unwrap_or(saved.alphabet),
+            lesson: self.lesson_config(saved.lesson),
+        }
+    }
+
+    fn fist_config(&self, saved: Fist) -> Fist {
+        let profile = self.fist.map_or(saved, FistProfile::fist);
+        Fist {
+            jitter: self.jitter.unwrap_or(profile.jitter),
+            dah_ratio: self.dah_ratio.unwrap_or(profile.dah_ratio),
+            weight: self.weight.unwrap_or(profile.weight),
+        }
+    }
+
+    fn lesson_config(&self, saved: LessonConfig) -> LessonConfig {
+        let length = match (self.groups, self.minutes) {
+            (Some(groups), _) => SessionLength::Groups(groups),
+            (None, Some(minutes)) => SessionLength::Minutes(minutes),
+            (None, None) => saved.length,
+        };
+        LessonConfig {
+            group_size: self.group_size.unwrap_or(saved.group_size),
+            length,
+        }
+    }
+}
+
+fn export(settings: &Settings, export_args: &ExportArgs) -> Result<(), Box<dyn Error>> {
+    let alphabet = settings.alphabet;
+    let total_lessons = alphabet.total_lessons();
+    let check_lesson = |n: usize| {
+        if (1..=total_lessons).contains(&n) {
+            Ok(n)
+        } else {
+            Err(format!("lesson must be between 1 and {}", total_lessons))
+        }
+    };
+
+    let text = if let Some(n) = export_args.lesson {
+        let timing = audio::MorseAudio::from_settings(&settings.audio, export_args.sample_rate);
+        let scores = scores::ScoreData::load()?;
+        lesson::lesson_text(
+            check_lesson(n)?,
+            alphabet,
+            &settings.lesson,
+            &scores,
+            |text| timing.duration(text),
+        )
+    } else if let Some(n) = export_args.practice {
+        lesson::practice_text(check_lesson(n)?, alphabet)
+    } else {
+        alphabet.normalize(export_args.text.as_deref().unwrap_or_default())
+    };
 
-    let mut app = App::new(args.wpm, args.effective_wpm, args.tone_freq)?;
-    app.run()?;
+    let (source, _) = audio::lesson_source(&text, &settings.audio, export_args.sample_rate)?;
+    let samples: Vec<f32> = source.collect();
+    wav::write_wav(&export_args.output, &samples, export_args.sample_rate)?;
+    println!("{}", text);
 
     Ok(())
 }
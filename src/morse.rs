@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, error::Error, fmt};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
 pub const KOCH_SEQUENCE: &[char] = &[
     'K', 'M', 'U', 'R', 'E', 'S', 'N', 'A', 'P', 'T', 'L', 'W', 'I', '.', 'J', 'Z', '=', 'F', 'O',
@@ -6,52 +9,539 @@ pub const KOCH_SEQUENCE: &[char] = &[
     '6', '0', 'X',
 ];
 
+// The other Koch sequences follow the Latin one: each letter is swapped for
+// the letter with the same code where there is one, and the letters with no
+// Latin counterpart come last. Letters that share a code can't be told
+// apart by ear, so only one of each is taught; the others can still be sent.
+
+pub const INTERNATIONAL_KOCH_SEQUENCE: &[char] = &[
+    'K', 'M', 'U', 'R', 'E', 'S', 'N', 'A', 'P', 'T', 'L', 'W', 'I', '.', 'J', 'Z', '=', 'F', 'O',
+    'Y', ',', 'V', 'G', '5', '/', 'Q', '9', '2', 'H', '3', '8', 'B', '?', '4', '7', 'C', '1', 'D',
+    '6', '0', 'X', 'Ä', 'Ö', 'Ü', 'É', 'Ñ', 'Å', 'Ç', 'È', 'Ś', 'Ź', 'Ż', 'Ð', 'Þ',
+];
+
+pub const CYRILLIC_KOCH_SEQUENCE: &[char] = &[
+    'К', 'М', 'У', 'Р', 'Е', 'С', 'Н', 'А', 'П', 'Т', 'Л', 'В', 'И', '.', 'Й', 'З', '=', 'Ф', 'О',
+    'Ы', ',', 'Ж', 'Г', '5', '/', 'Щ', '9', '2', 'Х', '3', '8', 'Б', '?', '4', '7', 'Ц', '1', 'Д',
+    '6', '0', 'Ь', 'Ч', 'Ш', 'Э', 'Ю', 'Я', 'Ъ',
+];
+
+pub const GREEK_KOCH_SEQUENCE: &[char] = &[
+    'Κ', 'Μ', 'Ρ', 'Ε', 'Σ', 'Ν', 'Α', 'Π', 'Τ', 'Λ', 'Ω', 'Ι', '.', 'Ζ', '=', 'Φ', 'Ο', 'Υ', ',',
+    'Γ', '5', '/', 'Ψ', '9', '2', 'Η', '3', '8', 'Β', '?', '4', '7', 'Θ', '1', 'Δ', '6', '0', 'Ξ',
+    'Χ',
+];
+
+pub const WABUN_KOCH_SEQUENCE: &[char] = &[
+    'ワ', 'ヨ', 'ウ', 'ナ', 'ヘ', 'ラ', 'タ', 'イ', 'ツ', 'ム', 'カ', 'ヤ', 'ヲ', 'フ', 'チ', 'レ',
+    'ケ', 'ク', 'リ', 'ネ', 'ヌ', 'ハ', 'ニ', 'ホ', 'マ', 'ノ', 'ロ', 'コ', 'ソ', 'ト', 'ル', 'ア',
+    'エ', 'オ', 'キ', 'サ', 'シ', 'ス', 'セ', 'テ', 'ヒ', 'ミ', 'メ', 'モ', 'ユ', 'ヰ', 'ヱ', 'ン',
+    '゛', '゜', 'ー',
+];
+
+const INTERNATIONAL_TABLE: &[(char, &str)] = &[
+    ('Ä', ".-.-"),
+    ('Å', ".--.-"),
+    ('Á', ".--.-"),
+    ('À', ".--.-"),
+    ('Ą', ".-.-"),
+    ('Ç', "-.-.."),
+    ('Ć', "-.-.."),
+    ('Ð', "..--."),
+    ('É', "..-.."),
+    ('È', ".-..-"),
+    ('Ę', "..-.."),
+    ('Ł', ".-..-"),
+    ('Ñ', "--.--"),
+    ('Ń', "--.--"),
+    ('Ö', "---."),
+    ('Ó', "---."),
+    ('Ø', "---."),
+    ('Ś', "...-..."),
+    ('Þ', ".--.."),
+    ('Ü', "..--"),
+    ('Ź', "--..-."),
+    ('Ż', "--..-"),
+];
+
+const CYRILLIC_TABLE: &[(char, &str)] = &[
+    ('А', ".-"),
+    ('Б', "-..."),
+    ('В', ".--"),
+    ('Г', "--."),
+    ('Д', "-.."),
+    ('Е', "."),
+    ('Ё', "."),
+    ('Ж', "...-"),
+    ('З', "--.."),
+    ('И', ".."),
+    ('Й', ".---"),
+    ('К', "-.-"),
+    ('Л', ".-.."),
+    ('М', "--"),
+    ('Н', "-."),
+    ('О', "---"),
+    ('П', ".--."),
+    ('Р', ".-."),
+    ('С', "..."),
+    ('Т', "-"),
+    ('У', "..-"),
+    ('Ф', "..-."),
+    ('Х', "...."),
+    ('Ц', "-.-."),
+    ('Ч', "---."),
+    ('Ш', "----"),
+    ('Щ', "--.-"),
+    ('Ъ', "--.--"),
+    ('Ы', "-.--"),
+    ('Ь', "-..-"),
+    ('Э', "..-.."),
+    ('Ю', "..--"),
+    ('Я', ".-.-"),
+];
+
+const GREEK_TABLE: &[(char, &str)] = &[
+    ('Α', ".-"),
+    ('Β', "-..."),
+    ('Γ', "--."),
+    ('Δ', "-.."),
+    ('Ε', "."),
+    ('Ζ', "--.."),
+    ('Η', "...."),
+    ('Θ', "-.-."),
+    ('Ι', ".."),
+    ('Κ', "-.-"),
+    ('Λ', ".-.."),
+    ('Μ', "--"),
+    ('Ν', "-."),
+    ('Ξ', "-..-"),
+    ('Ο', "---"),
+    ('Π', ".--."),
+    ('Ρ', ".-."),
+    ('Σ', "..."),
+    ('Τ', "-"),
+    ('Υ', "-.--"),
+    ('Φ', "..-."),
+    ('Χ', "----"),
+    ('Ψ', "--.-"),
+    ('Ω', ".--"),
+];
+
+const WABUN_TABLE: &[(char, &str)] = &[
+    ('ア', "--.--"),
+    ('イ', ".-"),
+    ('ウ', "..-"),
+    ('エ', "-.---"),
+    ('オ', ".-..."),
+    ('カ', ".-.."),
+    ('キ', "-.-.."),
+    ('ク', "...-"),
+    ('ケ', "-.--"),
+    ('コ', "----"),
+    ('サ', "-.-.-"),
+    ('シ', "--.-."),
+    ('ス', "---.-"),
+    ('セ', ".---."),
+    ('ソ', "---."),
+    ('タ', "-."),
+    ('チ', "..-."),
+    ('ツ', ".--."),
+    ('テ', ".-.--"),
+    ('ト', "..-.."),
+    ('ナ', ".-."),
+    ('ニ', "-.-."),
+    ('ヌ', "...."),
+    ('ネ', "--.-"),
+    ('ノ', "..--"),
+    ('ハ', "-..."),
+    ('ヒ', "--..-"),
+    ('フ', "--.."),
+    ('ヘ', "."),
+    ('ホ', "-.."),
+    ('マ', "-..-"),
+    ('ミ', "..-.-"),
+    ('ム', "-"),
+    ('メ', "-...-"),
+    ('モ', "-..-."),
+    ('ヤ', ".--"),
+    ('ユ', "-..--"),
+    ('ヨ', "--"),
+    ('ラ', "..."),
+    ('リ', "--."),
+    ('ル', "-.--."),
+    ('レ', "---"),
+    ('ロ', ".-.-"),
+    ('ワ', "-.-"),
+    ('ヰ', ".-..-"),
+    ('ヱ', ".--.."),
+    ('ヲ', ".---"),
+    ('ン', ".-.-."),
+    ('゛', ".."),
+    ('゜', "..--."),
+    ('ー', ".--.-"),
+    ('、', ".-.-.-"),
+];
+
+// Voiced kana are sent as the plain kana followed by a (han)dakuten.
+const VOICED_KANA: &str = "ガギグゲゴザジズゼゾダヂヅデドバビブベボ";
+const VOICED_BASE: &str = "カキクケコサシスセソタチツテトハヒフヘホ";
+const SEMI_VOICED_KANA: &str = "パピプペポ";
+const SEMI_VOICED_BASE: &str = "ハヒフヘホ";
+const SMALL_KANA: &str = "ァィゥェォッャュョヮ";
+const SMALL_BASE: &str = "アイウエオツヤユヨワ";
+
+const GREEK_TONOS: &str = "ΆΈΉΊΌΎΏΪΫ";
+const GREEK_PLAIN: &str = "ΑΕΗΙΟΥΩΙΥ";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum Alphabet {
+    /// A-Z, digits and punctuation
+    #[default]
+    Latin,
+    /// Latin plus accented letters (Ä, Ö, Ü, É, Ñ, ...)
+    International,
+    /// Russian Cyrillic
+    Cyrillic,
+    Greek,
+    /// Japanese kana
+    Wabun,
+}
+
+impl Alphabet {
+    pub fn koch_sequence(self) -> &'static [char] {
+        match self {
+            Alphabet::Latin => KOCH_SEQUENCE,
+            Alphabet::International => INTERNATIONAL_KOCH_SEQUENCE,
+            Alphabet::Cyrillic => CYRILLIC_KOCH_SEQUENCE,
+            Alphabet::Greek => GREEK_KOCH_SEQUENCE,
+            Alphabet::Wabun => WABUN_KOCH_SEQUENCE,
+        }
+    }
+
+    /// The letters this alphabet has on top of the Latin table.
+    pub fn table(self) -> &'static [(char, &'static str)] {
+        match self {
+            Alphabet::Latin => &[],
+            Alphabet::International => INTERNATIONAL_TABLE,
+            Alphabet::Cyrillic => CYRILLIC_TABLE,
+            Alphabet::Greek => GREEK_TABLE,
+            Alphabet::Wabun => WABUN_TABLE,
+        }
+    }
+
+    pub fn total_lessons(self) -> usize {
+        self.koch_sequence().len() - 1
+    }
+
+    /// Key for a lesson's scores. Latin keeps the bare lesson number used
+    /// before there were other alphabets.
+    pub fn lesson_key(self, lesson_num: usize) -> String {
+        match self {
+            Alphabet::Latin => lesson_num.to_string(),
+            _ => format!("{:?}-{}", self, lesson_num).to_lowercase(),
+        }
+    }
+
+    /// Brings typed or custom text into the form used by the code tables:
+    /// upper case, hiragana as katakana and voiced kana split into the kana
+    /// and its mark, so input from any keyboard layout or IME compares
+    /// equal to the lesson text.
+    pub fn normalize(self, text: &str) -> String {
+        let mut out = String::new();
+        for ch in text.chars().flat_map(char::to_uppercase) {
+            match self {
+                Alphabet::Wabun => push_kana(ch, &mut out),
+                Alphabet::Greek => out.push(replace_char(ch, GREEK_TONOS, GREEK_PLAIN)),
+                _ => out.push(ch),
+            }
+        }
+        out
+    }
+}
+
+fn replace_char(ch: char, from: &str, to: &str) -> char {
+    from.chars()
+        .position(|c| c == ch)
+        .and_then(|i| to.chars().nth(i))
+        .unwrap_or(ch)
+}
+
+fn push_kana(ch: char, out: &mut String) {
+    // hiragana sit 0x60 below their katakana
+    let ch = match ch {
+        'ぁ'..='ゖ' => char::from_u32(ch as u32 + 0x60).unwrap_or(ch),
+        _ => ch,
+    };
+    if VOICED_KANA.contains(ch) {
+        out.push(replace_char(ch, VOICED_KANA, VOICED_BASE));
+        out.push('゛');
+    } else if SEMI_VOICED_KANA.contains(ch) {
+        out.push(replace_char(ch, SEMI_VOICED_KANA, SEMI_VOICED_BASE));
+        out.push('゜');
+    } else {
+        out.push(replace_char(ch, SMALL_KANA, SMALL_BASE));
+    }
+}
+
+const LATIN_TABLE: &[(char, &str)] = &[
+    ('A', ".-"),
+    ('B', "-..."),
+    ('C', "-.-."),
+    ('D', "-.."),
+    ('E', "."),
+    ('F', "..-."),
+    ('G', "--."),
+    ('H', "...."),
+    ('I', ".."),
+    ('J', ".---"),
+    ('K', "-.-"),
+    ('L', ".-.."),
+    ('M', "--"),
+    ('N', "-."),
+    ('O', "---"),
+    ('P', ".--."),
+    ('Q', "--.-"),
+    ('R', ".-."),
+    ('S', "..."),
+    ('T', "-"),
+    ('U', "..-"),
+    ('V', "...-"),
+    ('W', ".--"),
+    ('X', "-..-"),
+    ('Y', "-.--"),
+    ('Z', "--.."),
+    ('0', "-----"),
+    ('1', ".----"),
+    ('2', "..---"),
+    ('3', "...--"),
+    ('4', "....-"),
+    ('5', "....."),
+    ('6', "-...."),
+    ('7', "--..."),
+    ('8', "---.."),
+    ('9', "----."),
+    (' ', " "),
+    ('.', ".-.-.-"),
+    ('=', "-...-"),
+    (',', "--..--"),
+    ('/', "-..-."),
+    ('?', "..--.."),
+    ('\'', ".----."),
+    ('!', "-.-.--"),
+    ('(', "-.--."),
+    (')', "-.--.-"),
+    ('&', ".-..."),
+    (':', "---..."),
+    (';', "-.-.-."),
+    ('+', ".-.-."),
+    ('-', "-....-"),
+    ('_', "..--.-"),
+    ('"', ".-..-."),
+    ('$', "...-..-"),
+    ('@', ".--.-."),
+    // sent the same as X, which it is read back as
+    ('×', "-..-"),
+];
+
+// Codes that are only ever sent as prosigns.
+const PROSIGN_TABLE: &[(&str, &str)] = &[
+    ("<SK>", "...-.-"),
+    ("<BK>", "-...-.-"),
+    ("<CT>", "-.-.-"),
+    ("<SOS>", "...---..."),
+];
+
 pub fn morse_map() -> HashMap<char, &'static str> {
-    [
-        ('A', ".-"),
-        ('B', "-..."),
-        ('C', "-.-."),
-        ('D', "-.."),
-        ('E', "."),
-        ('F', "..-."),
-        ('G', "--."),
-        ('H', "...."),
-        ('I', ".."),
-        ('J', ".---"),
-        ('K', "-.-"),
-        ('L', ".-.."),
-        ('M', "--"),
-        ('N', "-."),
-        ('O', "---"),
-        ('P', ".--."),
-        ('Q', "--.-"),
-        ('R', ".-."),
-        ('S', "..."),
-        ('T', "-"),
-        ('U', "..-"),
-        ('V', "...-"),
-        ('W', ".--"),
-        ('X', "-..-"),
-        ('Y', "-.--"),
-        ('Z', "--.."),
-        ('0', "-----"),
-        ('1', ".----"),
-        ('2', "..---"),
-        ('3', "...--"),
-        ('4', "....-"),
-        ('5', "....."),
-        ('6', "-...."),
-        ('7', "--..."),
-        ('8', "---.."),
-        ('9', "----."),
-        (' ', " "),
-        ('.', ".-.-.-"),
-        ('=', "-...-"),
-        (',', "--..--"),
-        ('/', "-..-."),
-        ('?', "..--.."),
-    ]
-    .iter()
-    .cloned()
-    .collect()
+    LATIN_TABLE
+        .iter()
+        .chain(INTERNATIONAL_TABLE)
+        .chain(CYRILLIC_TABLE)
+        .chain(GREEK_TABLE)
+        .chain(WABUN_TABLE)
+        .cloned()
+        .collect()
+}
+
+/// Code to text lookup for an alphabet. Its own letters win over the Latin
+/// letters that share their codes, and where a table gives several letters
+/// the same code the first one is used.
+pub fn decode_map(alphabet: Alphabet) -> HashMap<&'static str, String> {
+    let mut map: HashMap<&'static str, String> = PROSIGN_TABLE
+        .iter()
+        .map(|(name, code)| (*code, name.to_string()))
+        .collect();
+    for table in [LATIN_TABLE, alphabet.table()] {
+        let mut seen = Vec::new();
+        for (ch, code) in table {
+            if *code != " " && !seen.contains(code) {
+                seen.push(*code);
+                map.insert(*code, ch.to_string());
+            }
+        }
+    }
+    map
+}
+
+/// Turns dot/dash text back into characters. Characters are separated by
+/// spaces, and words by `/`, a new line or two or more spaces. A code with
+/// no character comes out as `[code]` so it stands out in the result.
+pub fn decode(code_text: &str, alphabet: Alphabet) -> String {
+    let map = decode_map(alphabet);
+    let normalized: String = code_text
+        .chars()
+        .map(|c| match c {
+            '·' | '•' | '*' => '.',
+            '_' | '−' | '–' | '—' => '-',
+            '|' | '\n' => '/',
+            c => c,
+        })
+        .collect();
+    let text = normalized
+        .split('/')
+        .flat_map(|part| part.split("  "))
+        .filter(|word| !word.trim().is_empty())
+        .map(|word| {
+            word.split_whitespace()
+                .map(|code| {
+                    map.get(code)
+                        .cloned()
+                        .unwrap_or_else(|| format!("[{}]", code))
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    match alphabet {
+        Alphabet::Wabun => compose_kana(&text),
+        _ => text,
+    }
+}
+
+/// Joins kana and a following (han)dakuten back into the voiced kana.
+fn compose_kana(text: &str) -> String {
+    let mut out = String::new();
+    for ch in text.chars() {
+        let base = out.chars().last().unwrap_or_default();
+        let voiced = match ch {
+            '゛' => VOICED_BASE
+                .contains(base)
+                .then(|| replace_char(base, VOICED_BASE, VOICED_KANA)),
+            '゜' => SEMI_VOICED_BASE
+                .contains(base)
+                .then(|| replace_char(base, SEMI_VOICED_BASE, SEMI_VOICED_KANA)),
+            _ => None,
+        };
+        match voiced {
+            Some(voiced) => {
+                out.pop();
+                out.push(voiced);
+            }
+            None => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Splits text into the symbols that are sent and scored as a unit: single
+/// characters, or a whole prosign. Letters written between angle brackets
+/// (`<AR>`, `<SK>`, `<KN>`) are sent run together without a character gap.
+pub fn symbols(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut rest = text;
+    while let Some(ch) = rest.chars().next() {
+        let len = prosign_len(rest).unwrap_or(ch.len_utf8());
+        out.push(rest[..len].to_string());
+        rest = &rest[len..];
+    }
+    out
+}
+
+/// The length in bytes of the prosign at the start of `text`, if there is
+/// one: a `<` closed by a `>` before any whitespace or another `<`. A stray
+/// `<` is just a character.
+fn prosign_len(text: &str) -> Option<usize> {
+    let body = text.strip_prefix('<')?;
+    let end = body.find(|c: char| c == '>' || c == '<' || c.is_whitespace())?;
+    body[end..].starts_with('>').then_some(end + 2)
+}
+
+/// Looks up the code for a symbol. A prosign's letters are joined with no
+/// gap between them; it has no code if any of them is unknown.
+pub fn encode_symbol(symbol: &str, map: &HashMap<char, &'static str>) -> Option<String> {
+    if let Some(letters) = symbol.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+        if letters.is_empty() {
+            return None;
+        }
+        return letters
+            .chars()
+            .map(|ch| map.get(&ch.to_ascii_uppercase()).filter(|c| **c != " "))
+            .collect::<Option<Vec<_>>>()
+            .map(|codes| codes.into_iter().copied().collect());
+    }
+    let mut chars = symbol.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => map.get(&ch).map(|code| code.to_string()),
+        _ => None,
+    }
+}
+
+/// Symbols in a text that have no Morse code.
+#[derive(Debug)]
+pub struct UnknownSymbols(pub Vec<String>);
+
+impl fmt::Display for UnknownSymbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list: Vec<String> = self.0.iter().map(|s| format!("{:?}", s)).collect();
+        write!(f, "no Morse code for {}", list.join(", "))
+    }
+}
+
+impl Error for UnknownSymbols {}
+
+/// Checks that every symbol in `text` can be sent, so nothing is silently
+/// skipped during playback.
+pub fn check_text(text: &str) -> Result<(), UnknownSymbols> {
+    let map = morse_map();
+    let mut unknown: Vec<String> = Vec::new();
+    for symbol in symbols(text) {
+        if encode_symbol(&symbol, &map).is_none() && !unknown.contains(&symbol) {
+            unknown.push(symbol);
+        }
+    }
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(UnknownSymbols(unknown))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn koch_letters_all_sound_different() {
+        let map = morse_map();
+        for alphabet in Alphabet::value_variants() {
+            let mut seen: HashMap<&str, char> = HashMap::new();
+            for &ch in alphabet.koch_sequence() {
+                let code = map[&ch];
+                if let Some(other) = seen.insert(code, ch) {
+                    panic!("{:?}: {} and {} are both {}", alphabet, other, ch, code);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prosigns_are_one_symbol() {
+        assert_eq!(symbols("K<AR> M"), ["K", "<AR>", " ", "M"]);
+    }
+
+    #[test]
+    fn unclosed_bracket_is_a_character() {
+        assert_eq!(symbols("<M KM"), ["<", "M", " ", "K", "M"]);
+        assert_eq!(symbols("K<"), ["K", "<"]);
+        assert_eq!(symbols("<<AR>"), ["<", "<AR>"]);
+    }
 }
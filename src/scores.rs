@@ -1,7 +1,363 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-#[derive(Serialize, Deserialize, Default)]
+use crate::{align::Edit, morse};
+
+/// Bumped whenever the layout of the scores file changes. Files without a
+/// version hold bare percentages per lesson.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+#[serde(from = "ScoreFile")]
 pub struct ScoreData {
-    pub lessons: HashMap<String, Vec<u32>>, // lesson number -> list of scores (%)
+    pub version: u32,
+    pub lessons: HashMap<String, Vec<ScoreRecord>>, // lesson number -> attempts
+    pub sending: HashMap<String, Vec<SendingScore>>, // lesson number -> sending attempts
+    pub chars: HashMap<String, CharStats>,          // character -> how it has been copied
+    pub confusions: HashMap<String, HashMap<String, u32>>, // sent -> typed instead -> count
+    /// set when the file was read in an older layout and needs writing back
+    #[serde(skip)]
+    migrated: bool,
+}
+
+/// One receiving attempt and the conditions it was copied under. Attempts
+/// migrated from bare percentages have only their accuracy, with nothing
+/// for the rest and empty text.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ScoreRecord {
+    /// seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    pub accuracy: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wpm: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effective_wpm: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tone_freq: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f32>,
+    pub expected: String,
+    pub typed: String,
+    /// speed and pitch changes made during the session, which started at
+    /// the speed and pitch above
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub speed_changes: Vec<SpeedChange>,
+}
+
+/// A change of speed or pitch partway through a session.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default)]
+pub struct SpeedChange {
+    /// seconds into the session
+    pub at_secs: f32,
+    pub wpm: u32,
+    pub effective_wpm: u32,
+    pub tone_freq: f32,
+}
+
+/// The scores file as found on disk, in any layout it has had.
+#[derive(Deserialize)]
+struct ScoreFile {
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
+    lessons: HashMap<String, Vec<StoredScore>>,
+    #[serde(default)]
+    sending: HashMap<String, Vec<SendingScore>>,
+    #[serde(default)]
+    chars: HashMap<String, CharStats>,
+    #[serde(default)]
+    confusions: HashMap<String, HashMap<String, u32>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredScore {
+    Percent(u32),
+    Record(ScoreRecord),
+}
+
+impl From<ScoreFile> for ScoreData {
+    fn from(file: ScoreFile) -> Self {
+        let lessons = file
+            .lessons
+            .into_iter()
+            .map(|(lesson, scores)| {
+                let records = scores
+                    .into_iter()
+                    .map(|score| match score {
+                        StoredScore::Percent(accuracy) => ScoreRecord {
+                            accuracy,
+                            ..Default::default()
+                        },
+                        StoredScore::Record(record) => record,
+                    })
+                    .collect();
+                (lesson, records)
+            })
+            .collect();
+        Self {
+            version: SCHEMA_VERSION,
+            lessons,
+            sending: file.sending,
+            chars: file.chars,
+            confusions: file.confusions,
+            migrated: file.version < SCHEMA_VERSION,
+        }
+    }
+}
+
+impl Default for ScoreData {
+    fn default() -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            lessons: HashMap::new(),
+            sending: HashMap::new(),
+            chars: HashMap::new(),
+            confusions: HashMap::new(),
+            migrated: false,
+        }
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+// how much the latest session counts towards a character's recent miss rate
+const RECENT_WEIGHT: f32 = 0.3;
+
+/// How a character has been copied. `sent` splits into `correct`,
+/// `substituted` (something else typed in its place) and `dropped` (nothing
+/// typed); `inserted` counts the times it was typed but never sent.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default)]
+pub struct CharStats {
+    pub sent: u32,
+    pub correct: u32,
+    pub substituted: u32,
+    pub dropped: u32,
+    pub inserted: u32,
+    /// share of the character missed in recent sessions, with older sessions
+    /// counting for less and less
+    pub recent_miss: f32,
+}
+
+impl CharStats {
+    pub fn missed(&self) -> u32 {
+        self.substituted + self.dropped
+    }
+}
+
+impl ScoreData {
+    /// Reads the scores file, bringing an older layout up to date on disk.
+    pub fn load() -> Result<Self, confy::ConfyError> {
+        let mut scores: Self = confy::load("moser", None)?;
+        if scores.migrated {
+            scores.store()?;
+            scores.migrated = false;
+        }
+        Ok(scores)
+    }
+
+    pub fn store(&self) -> Result<(), confy::ConfyError> {
+        confy::store("moser", None, self)
+    }
+
+    /// Adds one session's alignment of typed against sent text to the
+    /// per-character counts and the confusion counts. Word spaces aren't
+    /// counted, and neither are confusions with something that has no code,
+    /// since they couldn't be drilled.
+    pub fn record_chars(&mut self, edits: &[Edit]) {
+        let map = morse::morse_map();
+        let mut session: HashMap<&str, CharStats> = HashMap::new();
+        for edit in edits {
+            let (symbol, field): (&str, fn(&mut CharStats) -> &mut u32) = match edit {
+                Edit::Correct(s) => (s, |c| &mut c.correct),
+                Edit::Substituted { expected, .. } => (expected, |c| &mut c.substituted),
+                Edit::Dropped(s) => (s, |c| &mut c.dropped),
+                Edit::Inserted(s) => (s, |c| &mut c.inserted),
+            };
+            if symbol == " " {
+                continue;
+            }
+            if let Edit::Substituted { expected, typed } = edit
+                && typed != " "
+                && morse::encode_symbol(expected, &map).is_some()
+                && morse::encode_symbol(typed, &map).is_some()
+            {
+                *self
+                    .confusions
+                    .entry(expected.clone())
+                    .or_default()
+                    .entry(typed.clone())
+                    .or_default() += 1;
+            }
+            let stats = session.entry(symbol).or_default();
+            *field(stats) += 1;
+            if !matches!(edit, Edit::Inserted(_)) {
+                stats.sent += 1;
+            }
+        }
+        for (symbol, counts) in session {
+            let stats = self.chars.entry(symbol.to_string()).or_default();
+            stats.sent += counts.sent;
+            stats.correct += counts.correct;
+            stats.substituted += counts.substituted;
+            stats.dropped += counts.dropped;
+            stats.inserted += counts.inserted;
+            if counts.sent > 0 {
+                let missed = counts.missed() as f32 / counts.sent as f32;
+                stats.recent_miss += RECENT_WEIGHT * (missed - stats.recent_miss);
+            }
+        }
+    }
+
+    /// How often `typed` was copied when `sent` was sent.
+    pub fn confusion(&self, sent: &str, typed: &str) -> u32 {
+        self.confusions
+            .get(sent)
+            .and_then(|row| row.get(typed))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Pairs of characters mistaken for each other in either direction, most
+    /// confused first. Pairs with a character that has no code, which older
+    /// versions recorded, are left out.
+    pub fn confused_pairs(&self) -> Vec<(String, String, u32)> {
+        let map = morse::morse_map();
+        let sendable = |symbol: &str| morse::encode_symbol(symbol, &map).is_some();
+        let mut pairs: HashMap<(&str, &str), u32> = HashMap::new();
+        for (sent, row) in &self.confusions {
+            for (typed, &count) in row {
+                if !sendable(sent) || !sendable(typed) {
+                    continue;
+                }
+                let key = if sent <= typed {
+                    (sent.as_str(), typed.as_str())
+                } else {
+                    (typed.as_str(), sent.as_str())
+                };
+                *pairs.entry(key).or_default() += count;
+            }
+        }
+        let mut pairs: Vec<(String, String, u32)> = pairs
+            .into_iter()
+            .map(|((a, b), count)| (a.to_string(), b.to_string(), count))
+            .collect();
+        pairs.sort_by(|x, y| y.2.cmp(&x.2).then_with(|| (&x.0, &x.1).cmp(&(&y.0, &y.1))));
+        pairs
+    }
+
+    pub fn recent_miss(&self, symbol: &str) -> f32 {
+        self.chars
+            .get(symbol)
+            .map_or(0.0, |stats| stats.recent_miss)
+    }
+}
+
+/// A sending attempt: how much of the target came through, and the shape of
+/// the fist that sent it (lengths in dits).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SendingScore {
+    #[serde(default)]
+    pub timestamp: u64,
+    pub accuracy: u32,
+    pub wpm: f32,
+    pub dah_ratio: Option<f32>,
+    pub element_gap: Option<f32>,
+    pub char_gap: Option<f32>,
+    pub word_gap: Option<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::PathBuf};
+
+    fn scratch_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("moser-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn only_sendable_confusions_are_drilled() {
+        let substituted = |expected: &str, typed: &str| Edit::Substituted {
+            expected: expected.to_string(),
+            typed: typed.to_string(),
+        };
+        let mut scores = ScoreData::default();
+        scores.record_chars(&[substituted("K", "R"), substituted("M", "#")]);
+        assert_eq!(scores.confusion("M", "#"), 0);
+        scores
+            .confusions
+            .entry("S".to_string())
+            .or_default()
+            .insert("%".to_string(), 3);
+        assert_eq!(
+            scores.confused_pairs(),
+            vec![("K".to_string(), "R".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn migrates_bare_percentages() {
+        let path = scratch_file("legacy", "[lessons]\n\"1\" = [80, 90]\n");
+        let scores: ScoreData = confy::load_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(scores.migrated);
+        assert_eq!(scores.version, SCHEMA_VERSION);
+        let records = &scores.lessons["1"];
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].accuracy, 80);
+        assert_eq!(records[1].accuracy, 90);
+        assert!(records[0].wpm.is_none());
+        assert!(records[0].timestamp.is_none());
+    }
+
+    #[test]
+    fn current_files_round_trip() {
+        let mut scores = ScoreData::default();
+        scores
+            .lessons
+            .entry("1".to_string())
+            .or_default()
+            .push(ScoreRecord {
+                timestamp: Some(1_700_000_000),
+                accuracy: 95,
+                wpm: Some(20),
+                effective_wpm: Some(15),
+                tone_freq: Some(600.0),
+                duration_secs: Some(61.5),
+                expected: "KMKM".to_string(),
+                typed: "KMKK".to_string(),
+                speed_changes: vec![SpeedChange {
+                    at_secs: 10.0,
+                    wpm: 22,
+                    effective_wpm: 15,
+                    tone_freq: 600.0,
+                }],
+            });
+        scores.record_chars(&[Edit::Correct("K".to_string())]);
+        let path = scratch_file("current", "");
+        confy::store_path(&path, &scores).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+
+        let loaded: ScoreData = confy::load_path(&path).unwrap();
+        assert!(!loaded.migrated);
+        confy::store_path(&path, &loaded).unwrap();
+        let rewritten = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rewritten, written);
+    }
 }
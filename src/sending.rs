@@ -0,0 +1,146 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+use crate::{decoder::TimingDecoder, keyer::KeyerHandle, morse::Alphabet};
+
+/// A key contact the keyboard can work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lever {
+    Straight,
+    Dit,
+    Dah,
+}
+
+enum Key {
+    /// the sidetone follows the key, and we time it ourselves
+    Straight {
+        sidetone: Arc<AtomicBool>,
+        key_down_at: Option<Instant>,
+        key_up_at: Option<Instant>,
+    },
+    /// the keyer times the elements and reports them back
+    Iambic(KeyerHandle),
+}
+
+/// A sending practice session: what the operator keys is fed to a decoder as
+/// it happens, to be scored against `target`.
+pub struct SendingSession {
+    pub target: String,
+    pub decoder: TimingDecoder,
+    key: Key,
+}
+
+impl SendingSession {
+    pub fn straight(
+        target: String,
+        wpm: u32,
+        alphabet: Alphabet,
+        sidetone: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            target,
+            decoder: TimingDecoder::from_wpm(wpm, alphabet),
+            key: Key::Straight {
+                sidetone,
+                key_down_at: None,
+                key_up_at: None,
+            },
+        }
+    }
+
+    pub fn iambic(target: String, wpm: u32, alphabet: Alphabet, keyer: KeyerHandle) -> Self {
+        Self {
+            target,
+            decoder: TimingDecoder::from_wpm(wpm, alphabet),
+            key: Key::Iambic(keyer),
+        }
+    }
+
+    pub fn is_iambic(&self) -> bool {
+        matches!(self.key, Key::Iambic(_))
+    }
+
+    pub fn key_down(&mut self, lever: Lever, now: Instant) {
+        match &mut self.key {
+            Key::Straight {
+                sidetone,
+                key_down_at,
+                key_up_at,
+            } if lever == Lever::Straight => {
+                // held keys repeat; only the first press counts
+                if key_down_at.is_some() {
+                    return;
+                }
+                sidetone.store(true, Ordering::Relaxed);
+                if let Some(up) = key_up_at.take() {
+                    self.decoder.space((now - up).as_secs_f32());
+                }
+                *key_down_at = Some(now);
+            }
+            Key::Iambic(keyer) if lever != Lever::Straight => {
+                keyer.paddles.set(lever == Lever::Dah, true)
+            }
+            _ => {}
+        }
+    }
+
+    pub fn key_up(&mut self, lever: Lever, now: Instant) {
+        match &mut self.key {
+            Key::Straight {
+                sidetone,
+                key_down_at,
+                key_up_at,
+            } if lever == Lever::Straight => {
+                sidetone.store(false, Ordering::Relaxed);
+                if let Some(down) = key_down_at.take() {
+                    self.decoder.mark((now - down).as_secs_f32());
+                    *key_up_at = Some(now);
+                }
+            }
+            Key::Iambic(keyer) if lever != Lever::Straight => {
+                keyer.paddles.set(lever == Lever::Dah, false)
+            }
+            _ => {}
+        }
+    }
+
+    /// Catches the decoder up with the key, and lets it see the gap so far so
+    /// a character shows up as soon as the pause after it is long enough.
+    pub fn tick(&mut self, now: Instant) {
+        match &self.key {
+            Key::Straight {
+                key_up_at: Some(up),
+                ..
+            } => self.decoder.space((now - *up).as_secs_f32()),
+            Key::Straight { .. } => {}
+            Key::Iambic(keyer) => {
+                for element in keyer.elements.try_iter() {
+                    self.decoder.space(element.gap);
+                    self.decoder.mark(element.mark);
+                }
+                let idle = keyer.idle();
+                if idle > 0.0 {
+                    self.decoder.space(idle);
+                }
+            }
+        }
+    }
+
+    pub fn finish(&mut self) -> String {
+        self.tick(Instant::now());
+        match &self.key {
+            Key::Straight { sidetone, .. } => sidetone.store(false, Ordering::Relaxed),
+            Key::Iambic(keyer) => {
+                keyer.paddles.set(false, false);
+                keyer.paddles.set(true, false);
+            }
+        }
+        self.decoder.finish();
+        self.decoder.text().trim().to_string()
+    }
+}
@@ -0,0 +1,290 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::AudioSettings,
+    band::NoiseKind,
+    fist::FistProfile,
+    keyer::{IambicMode, KeyerConfig},
+    lesson::{GroupSize, LessonConfig, SessionLength},
+    morse::Alphabet,
+};
+
+const GROUP_SIZES: [GroupSize; 13] = [
+    GroupSize::Fixed(1),
+    GroupSize::Fixed(2),
+    GroupSize::Fixed(3),
+    GroupSize::Fixed(4),
+    GroupSize::Fixed(5),
+    GroupSize::Fixed(6),
+    GroupSize::Fixed(7),
+    GroupSize::Fixed(8),
+    GroupSize::Range(2, 4),
+    GroupSize::Range(3, 7),
+    GroupSize::Range(4, 8),
+    GroupSize::Range(5, 10),
+    GroupSize::Random,
+];
+
+const SESSION_LENGTHS: [SessionLength; 9] = [
+    SessionLength::Groups(5),
+    SessionLength::Groups(10),
+    SessionLength::Groups(20),
+    SessionLength::Groups(30),
+    SessionLength::Groups(50),
+    SessionLength::Minutes(1.0),
+    SessionLength::Minutes(2.0),
+    SessionLength::Minutes(5.0),
+    SessionLength::Minutes(10.0),
+];
+
+/// Preferences kept between runs, in moser's `settings` config file. Command
+/// line flags override them for a single run.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    pub keyer: KeyerConfig,
+    pub alphabet: Alphabet,
+    pub lesson: LessonConfig,
+}
+
+impl Settings {
+    pub fn load() -> Result<Self, confy::ConfyError> {
+        confy::load("moser", "settings")
+    }
+
+    pub fn store(&self) -> Result<(), confy::ConfyError> {
+        confy::store("moser", "settings", self)
+    }
+}
+
+/// A setting that can be changed from the settings screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    CharWpm,
+    EffectiveWpm,
+    Tone,
+    Volume,
+    GroupSize,
+    SessionLength,
+    Noise,
+    Snr,
+    Qsb,
+    Fist,
+    KeyerMode,
+}
+
+impl Field {
+    pub const ALL: [Field; 11] = [
+        Field::CharWpm,
+        Field::EffectiveWpm,
+        Field::Tone,
+        Field::Volume,
+        Field::GroupSize,
+        Field::SessionLength,
+        Field::Noise,
+        Field::Snr,
+        Field::Qsb,
+        Field::Fist,
+        Field::KeyerMode,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::CharWpm => "Char WPM",
+            Field::EffectiveWpm => "Effective WPM",
+            Field::Tone => "Tone",
+            Field::Volume => "Volume",
+            Field::GroupSize => "Group size",
+            Field::SessionLength => "Session length",
+            Field::Noise => "Noise",
+            Field::Snr => "SNR",
+            Field::Qsb => "QSB depth",
+            Field::Fist => "Fist",
+            Field::KeyerMode => "Keyer mode",
+        }
+    }
+
+    pub fn value(self, settings: &Settings) -> String {
+        let audio = &settings.audio;
+        match self {
+            Field::CharWpm => audio.wpm.to_string(),
+            Field::EffectiveWpm => audio.effective_wpm.to_string(),
+            Field::Tone => format!("{} Hz", audio.tone_freq),
+            Field::Volume => format!("{:.0}%", audio.volume * 100.0),
+            Field::GroupSize => settings.lesson.group_size.to_string(),
+            Field::SessionLength => match settings.lesson.length {
+                SessionLength::Groups(n) => format!("{} groups", n),
+                SessionLength::Minutes(m) => format!("{} min", m),
+            },
+            Field::Noise => format!("{:?}", audio.noise.kind),
+            Field::Snr => format!("{} dB", audio.noise.snr_db),
+            Field::Qsb => audio.fading.describe(),
+            Field::Fist => match fist_profile(settings) {
+                Some(profile) => format!("{:?}", profile),
+                None => audio.fist.describe(),
+            },
+            Field::KeyerMode => format!("{:?}", settings.keyer.mode),
+        }
+    }
+
+    /// Steps the setting up or down, or to the next or previous choice.
+    pub fn adjust(self, settings: &mut Settings, up: bool) {
+        let audio = &mut settings.audio;
+        let step = |value: f32, by: f32, min: f32, max: f32| {
+            let stepped = if up { value + by } else { value - by };
+            // round away float drift from repeated steps
+            let stepped = (stepped / by).round() * by;
+            // a value outside the range, as the command line allows, can be
+            // stepped back towards it but never jumps the other way
+            if up {
+                stepped.min(max.max(value))
+            } else {
+                stepped.max(min.min(value))
+            }
+        };
+        match self {
+            Field::CharWpm => {
+                audio.wpm = step(audio.wpm as f32, 1.0, 5.0, 60.0) as u32;
+                audio.effective_wpm = audio.effective_wpm.min(audio.wpm);
+            }
+            Field::EffectiveWpm => {
+                audio.effective_wpm = step(
+                    audio.effective_wpm as f32,
+                    1.0,
+                    1.0,
+                    audio.wpm.max(1) as f32,
+                ) as u32;
+            }
+            Field::Tone => audio.tone_freq = step(audio.tone_freq, 10.0, 300.0, 1200.0),
+            Field::Volume => audio.volume = step(audio.volume, 0.05, 0.0, 1.0),
+            Field::GroupSize => {
+                settings.lesson.group_size = cycle(&GROUP_SIZES, settings.lesson.group_size, up)
+            }
+            Field::SessionLength => {
+                settings.lesson.length = cycle(&SESSION_LENGTHS, settings.lesson.length, up)
+            }
+            Field::Noise => {
+                audio.noise.kind = cycle(NoiseKind::value_variants(), audio.noise.kind, up)
+            }
+            Field::Snr => audio.noise.snr_db = step(audio.noise.snr_db, 1.0, -10.0, 30.0),
+            Field::Qsb => audio.fading.depth = step(audio.fading.depth, 0.1, 0.0, 1.0),
+            Field::Fist => {
+                let profiles = FistProfile::value_variants();
+                let profile = match fist_profile(settings) {
+                    Some(profile) => cycle(profiles, profile, up),
+                    None => profiles[0],
+                };
+                settings.audio.fist = profile.fist();
+            }
+            Field::KeyerMode => {
+                settings.keyer.mode = cycle(IambicMode::value_variants(), settings.keyer.mode, up)
+            }
+        }
+    }
+
+    /// Copies this setting from `from` into `to`, leaving everything else
+    /// in `to` alone.
+    pub fn copy(self, from: &Settings, to: &mut Settings) {
+        let (src, dst) = (&from.audio, &mut to.audio);
+        match self {
+            Field::CharWpm => {
+                dst.wpm = src.wpm;
+                dst.effective_wpm = dst.effective_wpm.min(dst.wpm);
+            }
+            Field::EffectiveWpm => dst.effective_wpm = src.effective_wpm.min(dst.wpm),
+            Field::Tone => dst.tone_freq = src.tone_freq,
+            Field::Volume => dst.volume = src.volume,
+            Field::GroupSize => to.lesson.group_size = from.lesson.group_size,
+            Field::SessionLength => to.lesson.length = from.lesson.length,
+            Field::Noise => dst.noise.kind = src.noise.kind,
+            Field::Snr => dst.noise.snr_db = src.noise.snr_db,
+            Field::Qsb => dst.fading.depth = src.fading.depth,
+            Field::Fist => dst.fist = src.fist,
+            Field::KeyerMode => to.keyer.mode = from.keyer.mode,
+        }
+    }
+
+    /// Whether a change to this setting is worth hearing.
+    pub fn audible(self) -> bool {
+        !matches!(
+            self,
+            Field::GroupSize | Field::SessionLength | Field::KeyerMode
+        )
+    }
+}
+
+/// The preset fist the settings match, if any.
+fn fist_profile(settings: &Settings) -> Option<FistProfile> {
+    FistProfile::value_variants()
+        .iter()
+        .copied()
+        .find(|profile| profile.fist() == settings.audio.fist)
+}
+
+/// The choice after (or before) `current`, wrapping around. A value that
+/// isn't one of the choices moves to the first.
+fn cycle<T: Copy + PartialEq>(choices: &[T], current: T, up: bool) -> T {
+    let len = choices.len();
+    match choices.iter().position(|&choice| choice == current) {
+        Some(i) if up => choices[(i + 1) % len],
+        Some(i) => choices[(i + len - 1) % len],
+        None => choices[0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copying_a_field_leaves_the_rest_alone() {
+        let mut edited = Settings::default();
+        edited.audio.tone_freq = 700.0;
+        edited.audio.noise.kind = NoiseKind::White;
+        let mut saved = Settings::default();
+        Field::Tone.copy(&edited, &mut saved);
+        assert_eq!(saved.audio.tone_freq, 700.0);
+        assert_eq!(saved.audio.noise.kind, NoiseKind::Off);
+    }
+
+    #[test]
+    fn effective_speed_stays_within_the_saved_char_speed() {
+        let mut edited = Settings::default();
+        edited.audio.wpm = 30;
+        edited.audio.effective_wpm = 25;
+        let mut saved = Settings::default();
+        Field::EffectiveWpm.copy(&edited, &mut saved);
+        assert_eq!(saved.audio.wpm, 20);
+        assert_eq!(saved.audio.effective_wpm, 20);
+    }
+
+    #[test]
+    fn steps_never_go_the_wrong_way() {
+        let mut settings = Settings::default();
+        settings.audio.wpm = 3;
+        settings.audio.tone_freq = 1500.0;
+        Field::CharWpm.adjust(&mut settings, false);
+        Field::Tone.adjust(&mut settings, true);
+        assert_eq!(settings.audio.wpm, 3);
+        assert_eq!(settings.audio.tone_freq, 1500.0);
+        Field::CharWpm.adjust(&mut settings, true);
+        Field::Tone.adjust(&mut settings, false);
+        assert_eq!(settings.audio.wpm, 4);
+        assert_eq!(settings.audio.tone_freq, 1490.0);
+    }
+
+    #[test]
+    fn empty_lessons_in_the_settings_file_are_rejected() {
+        let path = std::env::temp_dir().join(format!("moser-length-{}.toml", std::process::id()));
+        for length in ["groups = 0", "minutes = 0.0", "minutes = -2.0"] {
+            std::fs::write(&path, format!("[lesson.length]\n{}\n", length)).unwrap();
+            assert!(confy::load_path::<Settings>(&path).is_err(), "{}", length);
+        }
+        std::fs::write(&path, "[lesson.length]\nminutes = 2.0\n").unwrap();
+        let settings: Settings = confy::load_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(settings.lesson.length, SessionLength::Minutes(2.0));
+    }
+}
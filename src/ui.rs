@@ -1,6 +1,8 @@
 use crate::{
     app::{App, Mode},
+    fist::FistReport,
     lesson,
+    settings::Field,
 };
 
 use ratatui::{
@@ -9,7 +11,7 @@ use ratatui::{
     style::{Color, Style},
     symbols,
     text::Text,
-    widgets::{Axis, Block, Borders, Chart, Clear, Dataset, Paragraph, Row, Table},
+    widgets::{Axis, Block, Borders, Cell, Chart, Clear, Dataset, Paragraph, Row, Table},
 };
 
 pub fn draw_ui(f: &mut Frame, app: &mut App, total_lessons: usize) {
@@ -28,7 +30,7 @@ pub fn draw_ui(f: &mut Frame, app: &mut App, total_lessons: usize) {
     let all_rows: Vec<Row> = (1..=total_lessons)
         .map(|i| {
             let lesson_str = format!("{}", i);
-            let new_chars = format!("{:?}", lesson::new_letters_for_lesson(i));
+            let new_chars = format!("{:?}", lesson::new_letters_for_lesson(i, app.alphabet));
             let mut row = Row::new(vec![lesson_str, new_chars]);
             if i - 1 == app.selected {
                 row = row.style(Style::default().fg(Color::Yellow));
@@ -58,7 +60,7 @@ pub fn draw_ui(f: &mut Frame, app: &mut App, total_lessons: usize) {
                     Style::default()
                 }),
         )
-        .widths(&[Constraint::Length(8), Constraint::Length(20)]);
+        .widths([Constraint::Length(8), Constraint::Length(20)]);
     f.render_widget(table, top_chunks[0]);
 
     let right_chunks = Layout::default()
@@ -68,9 +70,34 @@ pub fn draw_ui(f: &mut Frame, app: &mut App, total_lessons: usize) {
 
     let lesson_num = app.selected + 1;
 
+    let heading = match &app.drill {
+        Some((a, b)) if matches!(app.mode, Mode::TypingLesson) => format!("Drill {} ↔ {}", a, b),
+        _ => format!("Lesson {}", lesson_num),
+    };
+    // a session plays at its own speed, which the arrow keys change
+    let (audio, help) = if matches!(app.mode, Mode::TypingLesson | Mode::LetterPractice) {
+        (
+            &app.session_audio,
+            "Press ↑/↓ for char WPM, ←/→ for effective WPM\nPress PgUp/PgDn to change the tone",
+        )
+    } else {
+        (
+            &app.audio,
+            "Press <q> to quit\nPress <p> to hear letters, <s>/<a> to send, <c> for confusions\nPress <o> for settings, <n> to change noise, <i> for QRM",
+        )
+    };
     let details_text = format!(
-        "Lesson {}\n\nChar WPM: {}\nEffective WPM: {}\n\nPress <q> to quit\nPress <p> to hear letters",
-        lesson_num, app.wpm, app.effective_wpm
+        "{}\n\nChar WPM: {}\nEffective WPM: {}\nTone: {} Hz\nGroups: {}\nNoise: {}\nQSB: {}\nFist: {}\nQRM: {} station(s)\n\n{}",
+        heading,
+        audio.wpm,
+        audio.effective_wpm,
+        audio.tone_freq,
+        app.lesson.describe(),
+        app.audio.noise.describe(),
+        app.audio.fading.describe(),
+        app.audio.fist.describe(),
+        app.audio.qrm_stations,
+        help
     );
 
     let details = Paragraph::new(details_text)
@@ -85,11 +112,9 @@ pub fn draw_ui(f: &mut Frame, app: &mut App, total_lessons: usize) {
     let scores_vec: Vec<u32> = app
         .scores
         .lessons
-        .get(&lesson_num.to_string())
-        .unwrap_or(&Vec::new())
-        .iter()
-        .cloned()
-        .collect();
+        .get(&app.alphabet.lesson_key(lesson_num))
+        .map(|records| records.iter().map(|r| r.accuracy).collect())
+        .unwrap_or_default();
     let data: Vec<(f64, f64)> = scores_vec
         .iter()
         .enumerate()
@@ -156,19 +181,47 @@ pub fn draw_ui(f: &mut Frame, app: &mut App, total_lessons: usize) {
 
     let mut lines: Vec<Line> = Vec::new();
 
-    if let Some(spans) = &app.highlighted_results {
-        lines.push(Line::from(spans.clone()));
-        lines.push(Line::from(app.current_practice.clone()));
+    if let Some(session) = &app.sending {
+        lines.push(Line::from(session.target.clone()).style(Style::default().fg(Color::Gray)));
+        lines.push(Line::from(format!(
+            "{}{}_",
+            session.decoder.text(),
+            session.decoder.pending()
+        )));
+    } else if let Some(notice) = &app.notice {
+        lines.push(Line::from(notice.clone()).style(Style::default().fg(Color::Red)));
+    } else if let Some(results) = &app.highlighted_results {
+        lines.extend(results.iter().cloned());
     } else {
         lines.push(Line::from(display_input));
     }
 
+    let input_title = match (&app.sending, app.last_accuracy) {
+        (Some(session), _) if session.is_iambic() => format!(
+            "Paddles <z> dit, <x> dah, mode {:?} at {} wpm (Enter to score, Esc to stop)",
+            app.keyer.mode,
+            app.keyer.wpm(app.audio.wpm)
+        ),
+        (Some(session), _) => format!(
+            "Send with <space>, about {:.0} wpm (Enter to score, Esc to stop)",
+            session.decoder.wpm()
+        ),
+        (None, Some(accuracy)) => format!("Your Input ({}%)", accuracy),
+        (None, None) if matches!(app.mode, Mode::TypingLesson) && app.is_paused() => {
+            "Your Input (paused, Ctrl+P to resume)".to_string()
+        }
+        (None, None) if matches!(app.mode, Mode::TypingLesson) => {
+            "Your Input (Ctrl+P pause, Ctrl+R replay group, Ctrl+N restart)".to_string()
+        }
+        (None, None) => "Your Input".to_string(),
+    };
+
     let input_box = Paragraph::new(Text::from(lines))
         .block(
             Block::default()
-                .title("Your Input")
+                .title(input_title)
                 .borders(Borders::ALL)
-                .border_style(if matches!(app.mode, Mode::TypingLesson) {
+                .border_style(if matches!(app.mode, Mode::TypingLesson | Mode::Sending) {
                     Style::default().fg(Color::Cyan)
                 } else {
                     Style::default()
@@ -178,24 +231,220 @@ pub fn draw_ui(f: &mut Frame, app: &mut App, total_lessons: usize) {
         .style(Style::default().fg(Color::Green));
     f.render_widget(input_box, chunks[1]);
 
-    match app.mode {
-        Mode::LetterPractice => {
-            let area = centered_rect(60, 20, f.area());
-            f.render_widget(Clear, area);
-            let block = Block::default()
-                .title("Letter Practice (Esc to close)")
+    if let Mode::LetterPractice = app.mode {
+        let area = centered_rect(60, 20, f.area());
+        f.render_widget(Clear, area);
+        let block = Block::default()
+            .title("Letter Practice (Esc to close)")
+            .title_bottom(format!(
+                "{}/{} wpm, {} Hz (+/- speed)",
+                app.session_audio.wpm, app.session_audio.effective_wpm, app.session_audio.tone_freq
+            ))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Magenta))
+            .style(Style::default().bg(Color::Black));
+
+        let text = Paragraph::new(app.letter_practice.clone())
+            .block(block)
+            .alignment(Alignment::Center);
+
+        f.render_widget(text, area);
+    }
+
+    if let (Mode::SendingReport, Some(report)) = (&app.mode, &app.fist_report) {
+        draw_fist_report(f, report, app.last_accuracy.unwrap_or(0));
+    }
+
+    if let Mode::Confusions = app.mode {
+        draw_confusions(f, app);
+    }
+
+    if let Mode::Settings = app.mode {
+        draw_settings(f, app);
+    }
+}
+
+fn draw_settings(f: &mut Frame, app: &App) {
+    let settings = app.settings();
+    let rows: Vec<Row> = Field::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let row = Row::new(vec![field.label().to_string(), field.value(&settings)]);
+            if i == app.settings_selected {
+                row.style(Style::default().fg(Color::Yellow))
+            } else {
+                row
+            }
+        })
+        .collect();
+
+    let area = centered_rect(50, 60, f.area());
+    f.render_widget(Clear, area);
+    let table = Table::new(rows, [Constraint::Length(16), Constraint::Min(10)]).block(
+        Block::default()
+            .title("Settings (Esc to close)")
+            .title_bottom("←/→ change, <p> to preview")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Magenta))
+            .style(Style::default().bg(Color::Black)),
+    );
+    f.render_widget(table, area);
+}
+
+fn draw_confusions(f: &mut Frame, app: &App) {
+    let area = centered_rect(80, 80, f.area());
+    f.render_widget(Clear, area);
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+        .split(area);
+
+    let pairs = app.scores.confused_pairs();
+    let selected = pairs.get(app.confusion_selected);
+
+    // every character that has been mixed up with another
+    let mut chars: Vec<&String> = pairs.iter().flat_map(|(a, b, _)| [a, b]).collect();
+    chars.sort();
+    chars.dedup();
+
+    let header =
+        Row::new(std::iter::once("sent".to_string()).chain(chars.iter().map(|c| c.to_string())))
+            .style(Style::default().fg(Color::Cyan));
+    let rows: Vec<Row> = chars
+        .iter()
+        .map(|&sent| {
+            let cells = chars.iter().map(|&typed| {
+                let count = app.scores.confusion(sent, typed);
+                let text = if count == 0 {
+                    "·".to_string()
+                } else {
+                    count.to_string()
+                };
+                let in_pair = selected.is_some_and(|(a, b, _)| {
+                    (sent == a && typed == b) || (sent == b && typed == a)
+                });
+                let style = if in_pair {
+                    Style::default().fg(Color::Yellow)
+                } else if count > 0 {
+                    Style::default().fg(Color::Red)
+                } else {
+                    Style::default().fg(Color::DarkGray)
+                };
+                Cell::from(text).style(style)
+            });
+            Row::new(
+                std::iter::once(
+                    Cell::from(sent.to_string()).style(Style::default().fg(Color::Cyan)),
+                )
+                .chain(cells),
+            )
+        })
+        .collect();
+    let widths = std::iter::once(Constraint::Length(5)).chain(
+        chars
+            .iter()
+            .map(|c| Constraint::Length(c.chars().count().max(3) as u16)),
+    );
+    let matrix = Table::new(rows, widths).header(header).block(
+        Block::default()
+            .title("Confusions (row sent, column typed)")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Magenta))
+            .style(Style::default().bg(Color::Black)),
+    );
+    f.render_widget(matrix, panes[0]);
+
+    let mut lines: Vec<Line> = pairs
+        .iter()
+        .enumerate()
+        .map(|(i, (a, b, count))| {
+            let line = Line::from(format!("{} ↔ {}  {}", a, b, count));
+            if i == app.confusion_selected {
+                line.style(Style::default().fg(Color::Yellow))
+            } else {
+                line
+            }
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from("No confusions yet"));
+    }
+
+    // keep the selected pair in view
+    let height = panes[1].height.saturating_sub(2) as usize;
+    let scroll = (app.confusion_selected + 1).saturating_sub(height) as u16;
+    // the input pane, where notices usually go, is behind this popup
+    let notice = app.notice.clone().unwrap_or_default();
+    let list = Paragraph::new(Text::from(lines))
+        .scroll((scroll, 0))
+        .block(
+            Block::default()
+                .title("Pairs (Enter to drill, Esc to close)")
+                .title_bottom(Line::from(notice).style(Style::default().fg(Color::Red)))
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Magenta))
-                .style(Style::default().bg(Color::Black));
-
-            let text = Paragraph::new(app.letter_practice.clone())
-                .block(block)
-                .alignment(Alignment::Center);
+                .style(Style::default().bg(Color::Black)),
+        )
+        .alignment(Alignment::Center);
+    f.render_widget(list, panes[1]);
+}
 
-            f.render_widget(text, area);
-        }
-        _ => {}
+fn draw_fist_report(f: &mut Frame, report: &FistReport, accuracy: u32) {
+    let dits = |value: Option<f32>| match value {
+        Some(value) => format!("{:.1}", value),
+        None => "-".to_string(),
+    };
+    let mut lines = vec![
+        Line::from(format!(
+            "Accuracy {}%   Speed {:.0} → {:.0} wpm ({:+.0})",
+            accuracy,
+            report.start_wpm,
+            report.end_wpm,
+            report.end_wpm - report.start_wpm
+        )),
+        Line::from(format!(
+            "Dah:dit ratio {} (ideal 3.0)",
+            dits(report.dah_ratio)
+        )),
+        Line::from(format!(
+            "Spacing in dits: element {} (1), character {} (3), word {} (7)",
+            dits(report.element_gap),
+            dits(report.char_gap),
+            dits(report.word_gap)
+        )),
+        Line::from(""),
+        Line::from("Char  Sent  Timing error").style(Style::default().fg(Color::Cyan)),
+    ];
+    for (i, timing) in report.chars.iter().enumerate() {
+        // the three worst timed characters stand out
+        let style = if i < 3 && timing.error > 0.1 {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        };
+        lines.push(
+            Line::from(format!(
+                "{:<4}  {:>4}  {:>11.0}%",
+                timing.text,
+                timing.count,
+                timing.error * 100.0
+            ))
+            .style(style),
+        );
     }
+
+    let area = centered_rect(60, 60, f.area());
+    f.render_widget(Clear, area);
+    let block = Block::default()
+        .title("Sending Report (Esc to close)")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Magenta))
+        .style(Style::default().bg(Color::Black));
+    let text = Paragraph::new(Text::from(lines))
+        .block(block)
+        .alignment(Alignment::Center);
+    f.render_widget(text, area);
 }
 
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
@@ -0,0 +1,47 @@
+use std::{error::Error, path::Path};
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+/// Writes mono samples to a 16-bit PCM WAV file, which plays on just about
+/// anything (phones, car stereos, media players).
+pub fn write_wav(
+    path: impl AsRef<Path>,
+    samples: &[f32],
+    sample_rate: usize,
+) -> Result<(), Box<dyn Error>> {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: sample_rate as u32,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    for &sample in samples {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Reads a WAV file as mono samples in [-1, 1], averaging the channels of a
+/// stereo file. Returns the samples and the sample rate.
+pub fn read_wav(path: impl AsRef<Path>) -> Result<(Vec<f32>, usize), Box<dyn Error>> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let channels = spec.channels.max(1) as usize;
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    Ok((samples, spec.sample_rate as usize))
+}